@@ -0,0 +1,2669 @@
+use dbus;
+use notcurses::{Input,Received,Key};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path,PathBuf};
+use std::time::{Duration,Instant};
+
+use backend::{MachineBackend,BusEvent,Task,BindMount,TaskOutput,Pool,Image,ImageDetails,MachineDetails,ImportFormat,Transfer,SIGNALS,StartOptions,signal_number,unit_name,transient_unit_name,transient_machine,ephemeral_name,snapshot_source,check_image_name};
+use complete::complete_path;
+use config::{self,Config};
+use console::Console;
+use error::Error;
+use format::{format_size,parse_size};
+use form::{Form,FormEvent,Value};
+use keymap::{self,Action,Command};
+use procs::ProcessView;
+use journal::Journal;
+use logs::LogView;
+use nspawn::{self,Settings};
+use vt;
+
+// how long a container gets to shut down before we stop waiting for it
+const STOP_TIMEOUT: Duration = Duration::from_secs(30);
+// how often the process pane gets reloaded
+const PROCESS_REFRESH: Duration = Duration::from_secs(2);
+// how often the log pane looks for new entries
+const LOG_REFRESH: Duration = Duration::from_secs(1);
+// how often the progress of imports is read
+const TRANSFER_REFRESH: Duration = Duration::from_secs(1);
+// how often the resource usage of running machines is sampled
+const USAGE_REFRESH: Duration = Duration::from_secs(5);
+// samples closer together than this give a jumpy CPU%
+const CPU_MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+// what the UI loop has to do after an input was handled
+#[derive(Debug,PartialEq)]
+pub enum Outcome {
+    Nothing,
+    Redraw,
+    Update,
+    Quit,
+    // image and user, the UI has to step aside for the session
+    Shell(String, String),
+}
+
+// modal window on top of the list
+#[derive(Debug)]
+pub enum Popup {
+    MachineInfo(String, Result<MachineDetails, Error>),
+    Form(FormKind, Form),
+    // the question to show, Enter or 'y' confirm
+    Confirm(Confirm, String),
+    // title and text
+    Message(String, String),
+}
+
+// what to do with a form once it's submitted
+#[derive(Debug,PartialEq)]
+pub enum FormKind {
+    // name of the source image
+    Clone(String),
+    Rename(String),
+    Limit(String),
+    PoolLimit,
+    CleanPool,
+    Shell(String),
+    Console(String),
+    Copy(String),
+    Bind(String),
+    Kill(String),
+    // unit and process, None for all of the unit
+    Signal(String, Option<u32>),
+    // in the log pane
+    Search,
+    Settings(String),
+    Start(String),
+    Import,
+}
+
+// actions that need a confirmation
+#[derive(Debug,PartialEq)]
+pub enum Confirm {
+    Remove(String),
+    // mode for clean_pool
+    CleanPool(String),
+    Terminate(String),
+}
+
+// column the list is sorted by, all but the name with the largest first
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Sort {
+    Name,
+    Size,
+    Cpu,
+    Memory,
+    Tasks,
+    Io,
+}
+
+impl Sort {
+
+    pub fn next(self) -> Sort {
+        match self {
+            Sort::Name => Sort::Size,
+            Sort::Size => Sort::Cpu,
+            Sort::Cpu => Sort::Memory,
+            Sort::Memory => Sort::Tasks,
+            Sort::Tasks => Sort::Io,
+            Sort::Io => Sort::Name,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Sort::Name => "name",
+            Sort::Size => "size",
+            Sort::Cpu => "CPU",
+            Sort::Memory => "memory",
+            Sort::Tasks => "tasks",
+            Sort::Io => "IO",
+        }
+    }
+
+    fn key(self, img: &Image) -> u64 {
+        let usage = img.usage.unwrap_or_default();
+        match self {
+            Sort::Name => 0,
+            Sort::Size => img.size,
+            // in hundredths of a percent
+            Sort::Cpu => img.cpu_percent.map_or(0, |p| (p * 100.0) as u64),
+            Sort::Memory => usage.memory.unwrap_or(0),
+            Sort::Tasks => usage.tasks.unwrap_or(0),
+            Sort::Io => usage.io.unwrap_or(0),
+        }
+    }
+}
+
+// start or stop operation in flight for an image
+#[derive(Debug,PartialEq)]
+pub enum Pending {
+    Starting(dbus::Path<'static>),
+    Stopping(Instant),
+    // signal sent, terminate the machine if it's still there at the deadline
+    Killing(Instant),
+}
+
+pub struct App {
+    pub images: Vec<Image>,
+    pub current: usize,
+    pub pending: HashMap<String, Pending>,
+    pub status: Option<String>,
+    // what failed and why, shown until the next key press
+    pub error: Option<(String, Error)>,
+    // loaded on demand when an image gets selected
+    pub details: HashMap<String, Result<ImageDetails, Error>>,
+    pub popup: Option<Popup>,
+    // background operations that haven't reported back yet
+    pub tasks: Vec<Task>,
+    // advanced on every tick while tasks are running
+    pub spinner: usize,
+    // image to put the cursor on after the next update, e.g. a fresh clone
+    pub want_selected: Option<String>,
+    // not fatal if unavailable, only shown in the header
+    pub pool: Option<Pool>,
+    pub config: Config,
+    // tells the UI to write the config
+    pub config_changed: bool,
+    pub consoles: Vec<Console>,
+    // the console tab that gets the keys, None for the list
+    pub active: Option<usize>,
+    // columns and rows of the console pane, set by the UI
+    pub console_size: (usize, usize),
+    // process pane of a running machine
+    pub processes: Option<ProcessView>,
+    pub sort: Sort,
+    // CPU time, when it was taken and the CPU% it gave, per running image
+    pub cpu_samples: HashMap<String, (u64, Instant, Option<f64>)>,
+    pub usage_loaded: Instant,
+    // a machine started, the next tick samples right away
+    pub usage_due: bool,
+    // log pane of an image
+    pub log: Option<LogView>,
+    // where journald keeps the journals, persistent and volatile
+    pub journal_roots: Vec<PathBuf>,
+    // the host's journal is in a directory named after it
+    pub host_id: String,
+    // where systemd-nspawn looks for settings before the image's directory,
+    // edits go to the first
+    pub nspawn_dirs: Vec<PathBuf>,
+    // imports of importd, ours and those of others
+    pub transfers: Vec<Transfer>,
+    pub transfers_loaded: Instant,
+    // importd announced one that isn't in the list yet
+    pub unknown_transfers: bool,
+    // images that start at boot, asked again when systemd reports changes
+    pub autostart: Option<Vec<String>>,
+    // unit of each running machine, looked up once
+    pub units: HashMap<String, String>,
+    // image of each machine without one, an ephemeral run if there is one
+    pub sources: HashMap<String, Option<String>>,
+    // snapshots of ephemeral runs that ended without removing theirs are
+    // looked for after starting, refreshing and when the unit of a run is gone
+    pub snapshots_checked: bool,
+}
+
+// wording of the JobRemoved result, see org.freedesktop.systemd1(5)
+fn job_result(result: &str) -> &str {
+    match result {
+        "done" => "started",
+        "canceled" => "start canceled",
+        "timeout" => "start timed out",
+        "failed" => "failed to start",
+        "dependency" => "start failed, dependency failed",
+        "skipped" => "start skipped",
+        _ => result,
+    }
+}
+
+// section and key of each field of the settings form
+const SETTINGS_KEYS: &[(&str, &str)] = &[
+    ("Exec", "Boot"),
+    ("Exec", "PrivateUsers"),
+    ("Files", "Bind"),
+    ("Network", "VirtualEthernet"),
+    ("Network", "Port"),
+    ("Network", "Zone"),
+];
+
+// the keys that can be given more than once are space separated
+fn settings_form(name: &str, settings: &Settings) -> Form {
+    // values the form has no option for are kept as an extra one
+    let choice = |form: Form, label: &str, key: usize, options: &[&str]| {
+        let (section, key) = SETTINGS_KEYS[key];
+        let value = settings.get(section, key).map_or("default".to_string(), |v| nspawn::boolean(&v).to_string());
+        let mut options = options.to_vec();
+        if !options.contains(&value.as_str()) {
+            options.push(&value);
+        }
+        let selected = options.iter().position(|&o| o == value).unwrap_or(0);
+        form.choice(label, &options, selected)
+    };
+    let form = Form::new(&format!("Settings of {}", name));
+    let form = choice(form, "Boot", 0, &["default", "yes", "no"]);
+    let form = choice(form, "Private users", 1, &["default", "no", "yes", "pick", "identity"]);
+    let form = form.text("Bind", &settings.get_all("Files", "Bind").join(" "));
+    let form = choice(form, "Virtual ethernet", 3, &["default", "yes", "no"]);
+    form.text("Port", &settings.get_all("Network", "Port").join(" "))
+        .text("Zone", &settings.get("Network", "Zone").unwrap_or_default())
+}
+
+impl App {
+
+    pub fn new() -> App {
+        App { images: Vec::new(), current: 0, pending: HashMap::new(), status: None, error: None, details: HashMap::new(), popup: None, tasks: Vec::new(), spinner: 0, want_selected: None, pool: None, config: Config::default(), config_changed: false, consoles: Vec::new(), active: None, console_size: (80, 24), processes: None, sort: Sort::Name, cpu_samples: HashMap::new(), usage_loaded: Instant::now(), usage_due: false,
+              log: None, journal_roots: vec![PathBuf::from("/var/log/journal"), PathBuf::from("/run/log/journal")],
+              host_id: fs::read_to_string("/etc/machine-id").map(|id| id.trim().to_string()).unwrap_or_default(),
+              nspawn_dirs: vec![PathBuf::from("/etc/systemd/nspawn"), PathBuf::from("/run/systemd/nspawn")],
+              transfers: Vec::new(), transfers_loaded: Instant::now(), unknown_transfers: false, autostart: None, units: HashMap::new(),
+              sources: HashMap::new(), snapshots_checked: false }
+    }
+
+    pub fn selected(&self) -> Option<&Image> {
+        self.images.get(self.current)
+    }
+
+    pub fn update(&mut self, backend: &dyn MachineBackend) -> Result<(), Error> {
+        let mut running = HashMap::new();
+
+        for m in backend.list_machines()? {
+            running.insert(m.name.clone(), m);
+        }
+        self.units.retain(|name, _| running.contains_key(name));
+        let l = backend.list_images()?;
+        // keep the cursor on the same image if it is still there
+        let selected = self.want_selected.take().or_else(|| self.selected().map(|i| i.name.clone()));
+        // the accounting is sampled on ticks, until the next one the last sample stays
+        let sampled: HashMap<_, _> = self.images.drain(..).filter(|i| i.machine.is_some())
+            .map(|i| (i.name, (i.usage, i.cpu_percent, i.frozen))).collect();
+        let mut snapshots = Vec::new();
+        for mut img in l {
+            if img.name.starts_with('.') {
+                if let Some(source) = snapshot_source(&img.name) {
+                    snapshots.push((img.name.clone(), img.size, source.to_string()));
+                }
+                continue;
+            }
+            img.machine = running.remove(&img.name);
+            img.autostart = self.autostart.get_or_insert_with(|| backend.autostart().unwrap_or_default()).contains(&img.name);
+            self.images.push(img);
+        }
+        // of the machines without an image, the ephemeral runs go below theirs.
+        // Their sources are asked for once per machine
+        self.sources.retain(|name, _| running.contains_key(name));
+        let runs: Vec<Image> = running.into_iter().filter_map(|(name, m)| {
+            let source = self.sources.entry(name.clone()).or_insert_with(|| backend.machine_source(&name)).clone()?;
+            let source = self.images.iter().find(|i| i.name == source)?;
+            Some(Image { name, ro: false, t_created: 0, t_modified: 0, size: 0, machine: Some(m), autostart: false, frozen: false, source: Some(source.name.clone()), ..source.clone() })
+        }).collect();
+        self.images.extend(runs);
+        // a snapshot of an image nothing runs is left over, nspawn was killed
+        // before it could remove it. Those in use are locked and stay
+        if !self.snapshots_checked {
+            self.snapshots_checked = true;
+            let images = &self.images;
+            let stale: Vec<(String, u64)> = snapshots.into_iter()
+                .filter(|s| !images.iter().any(|i| i.machine.is_some() && (i.name == s.2 || i.source.as_ref() == Some(&s.2))))
+                .map(|s| (s.0, s.1)).collect();
+            if !stale.is_empty() {
+                self.start_task(backend, Task::RemoveSnapshots { snapshots: stale });
+            }
+        }
+        for img in self.images.iter_mut().filter(|i| i.machine.is_some()) {
+            match sampled.get(&img.name) {
+                Some(&(usage, cpu_percent, frozen)) => {
+                    img.usage = usage;
+                    img.cpu_percent = cpu_percent;
+                    img.frozen = frozen;
+                },
+                None => self.usage_due = true,
+            }
+        }
+        if self.unknown_transfers {
+            self.load_transfers(backend, Instant::now());
+        }
+        self.sort_images();
+        self.pool = backend.pool().ok();
+        let images = &self.images;
+        self.details.retain(|name, _| images.iter().any(|i| &i.name == name));
+        self.current = selected.and_then(|n| self.images.iter().position(|i| i.name == n)).unwrap_or(self.current);
+        self.current = self.current.min(self.images.len().saturating_sub(1));
+        Ok(())
+    }
+
+    // samples the accounting of the running machines, CPU% is relative to the previous sample
+    fn load_usage(&mut self, backend: &dyn MachineBackend, now: Instant) {
+        let mut samples = HashMap::new();
+        for img in self.images.iter_mut() {
+            img.usage = None;
+            img.cpu_percent = None;
+            img.frozen = false;
+            if img.machine.is_none() {
+                continue;
+            }
+            let unit = self.units.entry(img.name.clone()).or_insert_with(|| backend.machine_unit(&img.name));
+            let usage = match backend.unit_usage(unit) {
+                Ok(usage) => usage,
+                Err(_) => continue,
+            };
+            if let Some(cpu) = usage.cpu {
+                let sample = match self.cpu_samples.get(&img.name) {
+                    Some(&(prev, then, percent)) if now < then + CPU_MIN_INTERVAL => (prev, then, percent),
+                    Some(&(prev, then, _)) => {
+                        let elapsed = now.duration_since(then).as_nanos() as f64;
+                        (cpu, now, Some(cpu.saturating_sub(prev) as f64 * 100.0 / elapsed))
+                    },
+                    None => (cpu, now, None),
+                };
+                img.cpu_percent = sample.2;
+                samples.insert(img.name.clone(), sample);
+            }
+            img.frozen = usage.frozen;
+            img.usage = Some(usage);
+        }
+        self.cpu_samples = samples;
+        self.usage_loaded = now;
+        self.usage_due = false;
+    }
+
+    // ephemeral runs stay right below their image
+    fn sort_images(&mut self) {
+        let sort = self.sort;
+        let (mut runs, mut images): (Vec<Image>, Vec<Image>) = self.images.drain(..).partition(|i| i.source.is_some());
+        images.sort_by(|a, b| sort.key(b).cmp(&sort.key(a)).then_with(|| a.name.cmp(&b.name)));
+        runs.sort_by(|a, b| a.name.cmp(&b.name));
+        for img in images {
+            let own: Vec<Image> = runs.iter().filter(|r| r.source.as_ref() == Some(&img.name)).cloned().collect();
+            self.images.push(img);
+            self.images.extend(own);
+        }
+    }
+
+    // new transfers are looked up, the progress of all is read again
+    fn load_transfers(&mut self, backend: &dyn MachineBackend, now: Instant) {
+        self.transfers_loaded = now;
+        if self.unknown_transfers {
+            self.unknown_transfers = false;
+            for t in backend.list_transfers().unwrap_or_default() {
+                if !self.transfers.iter().any(|k| k.id == t.id) {
+                    self.transfers.push(t);
+                }
+            }
+        }
+        // negative while importd doesn't know
+        for t in self.transfers.iter_mut() {
+            t.progress = backend.transfer_progress(t).ok().filter(|&p| p >= 0.0);
+        }
+    }
+
+    // sorts again and keeps the cursor on the same image
+    fn resort(&mut self) {
+        let selected = self.selected().map(|i| i.name.clone());
+        self.sort_images();
+        self.current = selected.and_then(|n| self.images.iter().position(|i| i.name == n)).unwrap_or(0);
+    }
+
+    // like update but keeps the old list and reports the error
+    pub fn refresh(&mut self, backend: &dyn MachineBackend) {
+        if let Err(e) = self.update(backend) {
+            self.fail("Refreshing the image list failed".to_string(), e);
+        }
+    }
+
+    // ephemeral runs show the details of their image
+    pub fn load_details(&mut self, backend: &dyn MachineBackend) {
+        if let Some(name) = self.selected().map(|i| i.source.as_ref().unwrap_or(&i.name).clone()) {
+            self.details.entry(name).or_insert_with_key(|name| backend.image_details(name));
+        }
+    }
+
+    pub fn selected_details(&self) -> Option<&Result<ImageDetails, Error>> {
+        self.selected().and_then(|i| self.details.get(i.source.as_ref().unwrap_or(&i.name)))
+    }
+
+    // the terminals follow the size of their pane
+    pub fn set_console_size(&mut self, size: (usize, usize)) {
+        if size != self.console_size {
+            self.console_size = size;
+            for c in self.consoles.iter_mut().filter(|c| !c.exited) {
+                c.resize(size);
+            }
+        }
+    }
+
+    pub fn fail(&mut self, what: String, e: Error) {
+        self.error = Some((what, e));
+    }
+
+    pub fn handle_input(&mut self, backend: &dyn MachineBackend, input: &Input) -> Outcome {
+        let cmd = keymap::translate(input);
+        // forms get the keys before the keymap, except for dismissing errors
+        if cmd != Command::Ignore && self.error.is_none() {
+            match self.popup {
+                Some(Popup::Form(..)) => {
+                    self.status = None;
+                    return self.handle_form(backend, input.received);
+                },
+                Some(Popup::Confirm(..)) => {
+                    self.status = None;
+                    return self.handle_confirm(backend, input.received);
+                },
+                Some(_) => {},
+                None => if let Some(idx) = self.active {
+                    return self.console_input(idx, cmd, input);
+                } else if self.processes.is_some() {
+                    if let Some(outcome) = self.process_input(cmd) {
+                        return outcome;
+                    }
+                } else if self.log.is_some() {
+                    if let Some(outcome) = self.log_input(backend, cmd) {
+                        return outcome;
+                    }
+                },
+            }
+        }
+        self.handle_command(backend, cmd)
+    }
+
+    // everything but switching and closing tabs goes to the machine
+    fn console_input(&mut self, idx: usize, cmd: Command, input: &Input) -> Outcome {
+        match cmd {
+            Command::Action(Action::NextTab) => self.next_tab(),
+            Command::Action(Action::CloseTab) => self.close_tab(idx),
+            Command::Click(_) => Outcome::Nothing,
+            // any key closes a dead console
+            _ if self.consoles[idx].exited => self.close_tab(idx),
+            _ => {
+                self.consoles[idx].write(&vt::encode_key(input));
+                Outcome::Nothing
+            },
+        }
+    }
+
+    // moving around and signals are for the process pane, the rest goes to the list
+    fn process_input(&mut self, cmd: Command) -> Option<Outcome> {
+        let view = self.processes.as_mut()?;
+        match cmd {
+            Command::Action(Action::Up) => view.up(),
+            Command::Action(Action::Down) => view.down(),
+            Command::Unbound(Received::Key(Key::Esc)) => self.processes = None,
+            Command::Action(Action::Kill) => {
+                let row = view.selected()?.clone();
+                let title = match row.pid {
+                    Some(_) => format!("Send a signal to {}", row.text),
+                    None => format!("Send a signal to all processes of {}", view.name),
+                };
+                let signals: Vec<&str> = SIGNALS.iter().map(|s| s.0).collect();
+                let form = Form::new(&title).choice("Signal", &signals, 0);
+                self.popup = Some(Popup::Form(FormKind::Signal(view.unit.clone(), row.pid), form));
+            },
+            _ => return None,
+        }
+        Some(Outcome::Redraw)
+    }
+
+    // scrolling, filters and search for the log pane, the rest goes to the list
+    fn log_input(&mut self, backend: &dyn MachineBackend, cmd: Command) -> Option<Outcome> {
+        let page = self.console_size.1 as isize;
+        let view = self.log.as_mut()?;
+        match cmd {
+            Command::Action(Action::Up) => view.scroll(-1),
+            Command::Action(Action::Down) => view.scroll(1),
+            Command::Unbound(Received::Key(Key::PgUp)) => view.scroll(-page),
+            Command::Unbound(Received::Key(Key::PgDown)) => view.scroll(page),
+            Command::Unbound(Received::Key(Key::Esc)) => self.log = None,
+            Command::Unbound(Received::Char('f')) => view.toggle_follow(),
+            Command::Unbound(Received::Char(c @ '0'..='7')) => view.set_priority(c as u8 - b'0'),
+            Command::Unbound(Received::Char('/')) => {
+                let form = Form::new(&format!("Search in {}", view.title)).text("Text", view.search.as_deref().unwrap_or(""));
+                self.popup = Some(Popup::Form(FormKind::Search, form));
+            },
+            Command::Unbound(Received::Char('n')) => if !view.find() {
+                self.status = Some("no match".to_string());
+            },
+            Command::Unbound(Received::Char('m')) => {
+                let (name, machine) = (view.name.clone(), !view.machine);
+                return Some(self.open_log(backend, &name, machine));
+            },
+            _ => return None,
+        }
+        Some(Outcome::Redraw)
+    }
+
+    // the journal of the image's service, or the one inside the running machine
+    fn open_log(&mut self, backend: &dyn MachineBackend, name: &str, machine: bool) -> Outcome {
+        let now = Instant::now();
+        let view = if machine {
+            let m = match self.images.iter().find(|i| i.name == name).and_then(|i| i.machine.clone()) {
+                Some(m) => m,
+                None => {
+                    self.status = Some("machine is not running".to_string());
+                    return Outcome::Redraw;
+                },
+            };
+            let id = match backend.machine_details(&m) {
+                Ok(d) => d.id,
+                Err(e) => {
+                    self.fail(format!("Looking up the machine id of {} failed", name), e);
+                    return Outcome::Redraw;
+                },
+            };
+            // nspawn links the container's journal into the host's if it can,
+            // otherwise look into the container
+            let dirs: Vec<PathBuf> = self.journal_roots.iter().map(|r| r.join(&id)).filter(|d| d.is_dir()).collect();
+            let journal = if !dirs.is_empty() {
+                Journal::new(dirs, Vec::new())
+            } else {
+                match backend.open_root_directory(name) {
+                    Ok(f) => Journal::in_root(f, vec![PathBuf::from("var/log/journal").join(&id), PathBuf::from("run/log/journal").join(&id)], Vec::new()),
+                    Err(e) => {
+                        self.fail(format!("Opening the journal of {} failed", name), e);
+                        return Outcome::Redraw;
+                    },
+                }
+            };
+            LogView::new(name, true, &format!("journal of {}", name), journal, now)
+        } else {
+            let unit = backend.machine_unit(name);
+            let dirs = self.journal_roots.iter().map(|r| r.join(&self.host_id)).collect();
+            // what the service logged and what systemd logged about it
+            let matches = ["_SYSTEMD_UNIT", "UNIT", "OBJECT_SYSTEMD_UNIT"].iter().map(|f| (f.to_string(), unit.clone())).collect();
+            LogView::new(name, false, &unit, Journal::new(dirs, matches), now)
+        };
+        if view.lines.is_empty() {
+            self.status = Some(format!("no log entries for {}", view.title));
+        }
+        self.processes = None;
+        self.log = Some(view);
+        Outcome::Redraw
+    }
+
+    fn load_processes(&mut self, backend: &dyn MachineBackend, now: Instant) {
+        let result = match self.processes {
+            Some(ref view) => backend.unit_processes(&view.unit),
+            None => return,
+        };
+        match result {
+            Ok(procs) => if let Some(ref mut view) = self.processes {
+                view.reload(&procs, now);
+            },
+            Err(e) => {
+                self.processes = None;
+                self.fail("Listing processes failed".to_string(), e);
+            },
+        }
+    }
+
+    fn next_tab(&mut self) -> Outcome {
+        self.active = match self.active {
+            None if !self.consoles.is_empty() => Some(0),
+            Some(i) if i + 1 < self.consoles.len() => Some(i + 1),
+            _ => None,
+        };
+        Outcome::Redraw
+    }
+
+    fn close_tab(&mut self, idx: usize) -> Outcome {
+        self.consoles.remove(idx);
+        self.active = None;
+        Outcome::Redraw
+    }
+
+    // the pty of a console has something to read
+    pub fn console_output(&mut self, idx: usize) -> Outcome {
+        if self.consoles[idx].read() {
+            if self.active == Some(idx) { Outcome::Redraw } else { Outcome::Nothing }
+        } else {
+            let c = &self.consoles[idx];
+            self.status = Some(format!("{}: {} terminal closed", c.name, c.kind));
+            Outcome::Redraw
+        }
+    }
+
+    fn handle_confirm(&mut self, backend: &dyn MachineBackend, key: Received) -> Outcome {
+        let what = match self.popup.take() {
+            Some(Popup::Confirm(what, _)) => what,
+            p => {
+                self.popup = p;
+                return Outcome::Nothing;
+            },
+        };
+        match key {
+            Received::Key(Key::Enter) | Received::Char('y') => match what {
+                Confirm::Remove(name) => self.start_task(backend, Task::Remove { name }),
+                Confirm::CleanPool(mode) => self.start_task(backend, Task::CleanPool { mode }),
+                Confirm::Terminate(name) => match backend.terminate_machine(&name) {
+                    Ok(()) => {
+                        self.pending.insert(name, Pending::Stopping(Instant::now() + STOP_TIMEOUT));
+                    },
+                    Err(e) => self.fail(format!("Terminating {} failed", name), e),
+                },
+            },
+            _ => {},
+        }
+        Outcome::Redraw
+    }
+
+    fn handle_form(&mut self, backend: &dyn MachineBackend, key: Received) -> Outcome {
+        let event = match self.popup {
+            Some(Popup::Form(_, ref mut form)) => form.handle(key),
+            _ => return Outcome::Nothing,
+        };
+        match event {
+            FormEvent::Nothing => Outcome::Nothing,
+            FormEvent::Changed => Outcome::Redraw,
+            FormEvent::Complete => {
+                self.complete(backend);
+                Outcome::Redraw
+            },
+            FormEvent::Cancel => {
+                self.popup = None;
+                Outcome::Redraw
+            },
+            FormEvent::Submit => match self.popup.take() {
+                Some(Popup::Form(kind, mut form)) => {
+                    match self.submit(backend, &kind, &form) {
+                        Ok(outcome) => outcome,
+                        Err(msg) => {
+                            // keep the form open so the input can be fixed
+                            form.message = Some(msg);
+                            self.popup = Some(Popup::Form(kind, form));
+                            Outcome::Redraw
+                        },
+                    }
+                },
+                _ => Outcome::Nothing,
+            },
+        }
+    }
+
+    // Tab in a path field of the form
+    fn complete(&mut self, backend: &dyn MachineBackend) {
+        let (kind, mut form) = match self.popup.take() {
+            Some(Popup::Form(kind, form)) => (kind, form),
+            p => {
+                self.popup = p;
+                return;
+            },
+        };
+        let input = form.get_text(form.focus).to_string();
+        let machine = match kind {
+            FormKind::Copy(ref name) if form.focus == 2 => Some(name.as_str()),
+            FormKind::Bind(ref name) if form.focus == 1 => Some(name.as_str()),
+            _ => None,
+        };
+        let result = match machine {
+            // look around in the container through its root directory
+            Some(name) => backend.open_root_directory(name).map_err(|e| e.to_string()).and_then(|root| {
+                let root = PathBuf::from(format!("/proc/self/fd/{}", root.as_raw_fd()));
+                complete_path(&root, &input).map_err(|e| e.to_string())
+            }),
+            None => {
+                let root = if input.starts_with('/') { Ok(PathBuf::from("/")) } else { env::current_dir() };
+                root.and_then(|root| complete_path(&root, &input)).map_err(|e| e.to_string())
+            },
+        };
+        match result {
+            Ok((completed, candidates)) => {
+                form.set_text(form.focus, &completed);
+                form.message = if candidates.is_empty() { None } else { Some(candidates.join("  ")) };
+            },
+            Err(e) => form.message = Some(e),
+        }
+        self.popup = Some(Popup::Form(kind, form));
+    }
+
+    // Err is a message for the user to fix the input
+    fn submit(&mut self, backend: &dyn MachineBackend, kind: &FormKind, form: &Form) -> Result<Outcome, String> {
+        match *kind {
+            FormKind::Clone(ref name) => {
+                let new_name = form.get_text(0).trim();
+                self.check_new_name(new_name)?;
+                self.start_task(backend, Task::Clone { name: name.clone(), new_name: new_name.to_string(), read_only: form.get_toggle(1) });
+                Ok(Outcome::Redraw)
+            },
+            FormKind::Rename(ref name) => {
+                let new_name = form.get_text(0).trim();
+                if new_name == name {
+                    return Ok(Outcome::Redraw);
+                }
+                self.check_new_name(new_name)?;
+                match backend.rename_image(name, new_name) {
+                    Ok(()) => {
+                        self.status = Some(format!("{}: renamed to {}", name, new_name));
+                        self.want_selected = Some(new_name.to_string());
+                    },
+                    Err(e) => self.fail(format!("Renaming {} failed", name), e),
+                }
+                Ok(Outcome::Update)
+            },
+            FormKind::Limit(ref name) => self.set_limit(backend, Some(name), form.get_text(0)),
+            FormKind::PoolLimit => self.set_limit(backend, None, form.get_text(0)),
+            FormKind::CleanPool => self.preview_clean(backend, form.get_choice(0)),
+            FormKind::Console(ref name) => {
+                let kind = form.get_choice(0);
+                match backend.open_console(name, kind == "login") {
+                    Ok(pty) => {
+                        self.consoles.push(Console::new(name, kind, pty, self.console_size));
+                        self.active = Some(self.consoles.len() - 1);
+                    },
+                    Err(e) => self.fail(format!("Opening a terminal in {} failed", name), e),
+                }
+                Ok(Outcome::Redraw)
+            },
+            FormKind::Copy(ref name) => {
+                let to_machine = form.get_choice(0) == "to machine";
+                let (host, path) = (form.get_text(1).trim(), form.get_text(2).trim());
+                if host.is_empty() || path.is_empty() {
+                    return Err("both paths are needed".to_string());
+                }
+                if !path.starts_with('/') {
+                    return Err(format!("{} is not an absolute path", path));
+                }
+                // machined resolves relative paths against its own directory
+                let host = env::current_dir().map(|cwd| cwd.join(host)).unwrap_or_else(|_| PathBuf::from(host));
+                if to_machine && !host.exists() {
+                    return Err(format!("{} does not exist", host.display()));
+                }
+                let host = host.to_string_lossy().into_owned();
+                self.start_task(backend, Task::Copy { name: name.clone(), to_machine, host, path: path.to_string(), replace: form.get_toggle(3) });
+                Ok(Outcome::Redraw)
+            },
+            FormKind::Bind(ref name) => {
+                let source = form.get_text(0).trim();
+                let destination = match form.get_text(1).trim() {
+                    "" => source,
+                    d => d,
+                };
+                let bind = BindMount {
+                    source: source.to_string(),
+                    destination: destination.to_string(),
+                    read_only: form.get_toggle(2),
+                    mkdir: form.get_toggle(3),
+                };
+                for path in &[&bind.source, &bind.destination] {
+                    if !path.starts_with('/') {
+                        return Err(format!("'{}' is not an absolute path", path));
+                    }
+                }
+                if !PathBuf::from(&bind.source).is_dir() {
+                    return Err(format!("{} is not a directory", bind.source));
+                }
+                if let Err(e) = backend.bind_mount(name, &bind) {
+                    self.fail(format!("Bind mounting {} into {} failed", bind.source, name), e);
+                    return Ok(Outcome::Redraw);
+                }
+                self.status = Some(format!("{}: {} mounted at {}", name, bind.source, bind.destination));
+                if form.get_toggle(4) {
+                    let saved = self.config.binds.entry(name.clone()).or_default();
+                    if !saved.contains(&bind) {
+                        saved.push(bind);
+                        self.config_changed = true;
+                    }
+                }
+                Ok(Outcome::Redraw)
+            },
+            FormKind::Kill(ref name) => {
+                let who = form.get_choice(0);
+                let signal = form.get_choice(1);
+                let escalate = if form.get_toggle(2) {
+                    match form.get_text(3).trim().parse::<u64>() {
+                        Ok(secs) if secs > 0 => Some(Duration::from_secs(secs)),
+                        _ => return Err(format!("'{}' is not a number of seconds", form.get_text(3).trim())),
+                    }
+                } else {
+                    None
+                };
+                match backend.kill_machine(name, who, signal_number(signal).unwrap_or(SIGNALS[0].1)) {
+                    Ok(()) => {
+                        self.status = Some(format!("{}: {} sent to {}", name, signal, who));
+                        if let Some(timeout) = escalate {
+                            self.pending.insert(name.clone(), Pending::Killing(Instant::now() + timeout));
+                        }
+                    },
+                    Err(e) => self.fail(format!("Sending {} to {} failed", signal, name), e),
+                }
+                Ok(Outcome::Redraw)
+            },
+            FormKind::Signal(ref unit, pid) => {
+                let signal = form.get_choice(0);
+                let number = signal_number(signal).unwrap_or(SIGNALS[0].1);
+                let result = match pid {
+                    Some(pid) => backend.kill_process(pid, number),
+                    None => backend.kill_unit(unit, "all", number),
+                };
+                let whom = pid.map_or_else(|| unit.clone(), |p| p.to_string());
+                match result {
+                    Ok(()) => self.status = Some(format!("{} sent to {}", signal, whom)),
+                    Err(e) => self.fail(format!("Sending {} to {} failed", signal, whom), e),
+                }
+                self.load_processes(backend, Instant::now());
+                Ok(Outcome::Redraw)
+            },
+            FormKind::Search => {
+                let text = form.get_text(0).trim();
+                if let Some(ref mut view) = self.log {
+                    view.search = if text.is_empty() { None } else { Some(text.to_string()) };
+                    if view.search.is_some() && !view.find() {
+                        return Err(format!("'{}' not found", text));
+                    }
+                }
+                Ok(Outcome::Redraw)
+            },
+            FormKind::Settings(ref name) => {
+                let (source, mut settings) = self.load_settings(backend, name).map_err(|e| e.to_string())?;
+                // only what was changed in the form gets written, the rest stays as it is
+                let before = settings_form(name, &settings);
+                // nspawn ignores privileged keys like Bind= in a file next to the
+                // image, in /etc they would count. So none of it is copied
+                if source.is_some_and(|s| !self.nspawn_dirs.iter().any(|d| s.starts_with(d))) {
+                    settings = Settings::default();
+                }
+                let mut changed = false;
+                for (i, &(section, key)) in SETTINGS_KEYS.iter().enumerate() {
+                    if form.fields[i].value == before.fields[i].value {
+                        continue;
+                    }
+                    let values: Vec<String> = match form.fields[i].value {
+                        Value::Choice(..) => Some(form.get_choice(i)).filter(|&v| v != "default").map(|v| v.to_string()).into_iter().collect(),
+                        _ => form.get_text(i).split_whitespace().map(|v| v.to_string()).collect(),
+                    };
+                    for value in &values {
+                        nspawn::check(key, value)?;
+                    }
+                    settings.set_all(section, key, &values);
+                    changed = true;
+                }
+                if !changed {
+                    self.status = Some(format!("{}: settings unchanged", name));
+                    return Ok(Outcome::Redraw);
+                }
+                // a file in /etc hides the others, so one from /run is copied there
+                let target = self.nspawn_dirs[0].join(format!("{}.nspawn", name));
+                match settings.save(&target) {
+                    Ok(()) => {
+                        let running = self.images.iter().any(|i| &i.name == name && i.machine.is_some());
+                        self.status = Some(format!("{}: settings written to {}{}", name, target.display(), if running { ", used on the next start" } else { "" }));
+                    },
+                    Err(e) => self.fail(format!("Writing {} failed", target.display()), e.into()),
+                }
+                Ok(Outcome::Redraw)
+            },
+            FormKind::Start(ref name) => {
+                let mut binds = Vec::new();
+                for spec in form.get_text(2).split_whitespace() {
+                    let bind = match BindMount::parse(spec) {
+                        Some(b) if b.source.starts_with('/') && b.destination.starts_with('/') => b,
+                        _ => return Err(format!("'{}' is not like /host/dir:/machine/dir:ro", spec)),
+                    };
+                    if !PathBuf::from(&bind.source).exists() {
+                        return Err(format!("{} does not exist", bind.source));
+                    }
+                    binds.push(bind);
+                }
+                // nspawn names the bridge vz-<zone>, interface names have 15 characters at most
+                let zone = form.get_text(3).trim();
+                if zone.len() > 12 || !zone.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+                    return Err(format!("'{}' is not a zone name of up to 12 letters and digits", zone));
+                }
+                let target = form.get_text(4).trim();
+                if !target.is_empty() && !target.ends_with(".target") {
+                    return Err(format!("{} is not a target", target));
+                }
+                let options = StartOptions {
+                    ephemeral: form.get_toggle(0),
+                    read_only: form.get_toggle(1),
+                    binds,
+                    zone: Some(zone.to_string()).filter(|z| !z.is_empty()),
+                    target: Some(target.to_string()).filter(|t| !t.is_empty()),
+                    directory: None,
+                    source: None,
+                };
+                match backend.start_machine_with(name, &options) {
+                    Ok(job) => { self.pending.insert(name.clone(), Pending::Starting(job)); },
+                    Err(e) => self.fail(format!("Starting {} failed", name), e),
+                }
+                Ok(Outcome::Redraw)
+            },
+            FormKind::Import => {
+                let source = form.get_text(0).trim();
+                if source.is_empty() {
+                    return Err("a file or directory to import is needed".to_string());
+                }
+                // importd gets it opened, relative paths are fine
+                let source = env::current_dir().map(|cwd| cwd.join(source)).unwrap_or_else(|_| PathBuf::from(source));
+                if !source.exists() {
+                    return Err(format!("{} does not exist", source.display()));
+                }
+                let format = ImportFormat::parse(form.get_choice(1)).unwrap_or_else(|| ImportFormat::guess(&source));
+                if (format == ImportFormat::Directory) != source.is_dir() {
+                    return Err(format!("{} is {}a directory", source.display(), if source.is_dir() { "" } else { "not " }));
+                }
+                let name = match form.get_text(2).trim() {
+                    "" => ImportFormat::image_name(&source),
+                    name => name.to_string(),
+                };
+                let force = form.get_toggle(4);
+                if force {
+                    check_image_name(&name)?;
+                } else {
+                    self.check_new_name(&name)?;
+                }
+                match backend.import_image(format, &source.to_string_lossy(), &name, force, form.get_toggle(3)) {
+                    Ok(transfer) => {
+                        if !self.transfers.iter().any(|t| t.id == transfer.id) {
+                            self.transfers.push(transfer);
+                        }
+                        self.status = None;
+                    },
+                    Err(e) => self.fail(format!("Importing {} failed", source.display()), e),
+                }
+                Ok(Outcome::Redraw)
+            },
+            FormKind::Shell(ref name) => {
+                let user = form.get_text(0).trim();
+                if user.is_empty() {
+                    return Err("user name must not be empty".to_string());
+                }
+                if self.config.shell_users.get(name).map(|u| u.as_str()) != Some(user) {
+                    self.config.shell_users.insert(name.clone(), user.to_string());
+                    self.config_changed = true;
+                }
+                Ok(Outcome::Shell(name.clone(), user.to_string()))
+            },
+        }
+    }
+
+    // the settings systemd-nspawn uses for an image and where they are from,
+    // None if there are none yet
+    fn load_settings(&self, backend: &dyn MachineBackend, name: &str) -> Result<(Option<PathBuf>, Settings), Error> {
+        let image = backend.image_path(name).ok().map(PathBuf::from);
+        match nspawn::locate(&self.nspawn_dirs, image.as_deref(), name) {
+            Some(path) => Ok((Some(path.clone()), Settings::load(&path).map_err(|e| Error::Io(format!("{}: {}", path.display(), e)))?)),
+            None => Ok((None, Settings::default())),
+        }
+    }
+
+    // image None means the whole pool
+    fn set_limit(&mut self, backend: &dyn MachineBackend, image: Option<&str>, input: &str) -> Result<Outcome, String> {
+        let limit = match input.trim() {
+            "" | "-" | "none" => None,
+            s => Some(parse_size(s)?),
+        };
+        let size = limit.unwrap_or(u64::MAX);
+        let (what, result) = match image {
+            Some(name) => (name, backend.set_image_limit(name, size)),
+            None => ("pool", backend.set_pool_limit(size)),
+        };
+        match result {
+            Ok(()) => {
+                self.status = Some(match limit {
+                    Some(l) => format!("{}: limited to {}", what, format_size(l)),
+                    None => format!("{}: no limit", what),
+                });
+                if let Some(name) = image {
+                    self.details.remove(name);
+                }
+            },
+            Err(e) => self.fail(format!("Setting the limit of {} failed", what), e),
+        }
+        Ok(Outcome::Update)
+    }
+
+    // ask before removing, machined has no dry run so guess what it would do
+    fn preview_clean(&mut self, backend: &dyn MachineBackend, mode: &str) -> Result<Outcome, String> {
+        let listed = backend.list_images().and_then(|i| Ok((i, backend.list_machines()?)));
+        let (images, machines) = match listed {
+            Ok(l) => l,
+            Err(e) => {
+                self.fail("Listing images failed".to_string(), e);
+                return Ok(Outcome::Redraw);
+            },
+        };
+        let doomed: Vec<&Image> = images.iter().filter(|i| {
+            (mode == "all" || i.name.starts_with('.')) && !machines.iter().any(|m| m.name == i.name)
+        }).collect();
+        if doomed.is_empty() {
+            return Err(format!("there are no {} images to remove", mode));
+        }
+        let total: u64 = doomed.iter().map(|i| i.size).sum();
+        let mut question = format!("Remove {} images ({})? This can not be undone.\n", doomed.len(), format_size(total));
+        for i in doomed {
+            question += &format!("\n  {} {}", i.name, format_size(i.size));
+        }
+        self.popup = Some(Popup::Confirm(Confirm::CleanPool(mode.to_string()), question));
+        Ok(Outcome::Redraw)
+    }
+
+    fn check_new_name(&self, name: &str) -> Result<(), String> {
+        check_image_name(name)?;
+        if self.images.iter().any(|i| i.name == name) {
+            return Err(format!("{} already exists", name));
+        }
+        Ok(())
+    }
+
+    fn start_task(&mut self, backend: &dyn MachineBackend, task: Task) {
+        self.tasks.push(task.clone());
+        backend.spawn(task);
+    }
+
+    // unused name for a copy of the image
+    fn clone_name(&self, name: &str) -> String {
+        let mut new_name = format!("{}-clone", name);
+        let mut n = 1;
+        while self.images.iter().any(|i| i.name == new_name) {
+            n += 1;
+            new_name = format!("{}-clone{}", name, n);
+        }
+        new_name
+    }
+
+    pub fn handle_command(&mut self, backend: &dyn MachineBackend, cmd: Command) -> Outcome {
+        if cmd == Command::Ignore {
+            return Outcome::Nothing;
+        }
+        self.status = None;
+        if self.error.is_some() {
+            // any key dismisses the error
+            self.error = None;
+            return Outcome::Redraw;
+        }
+        if self.popup.is_some() {
+            self.popup = None;
+            return Outcome::Redraw;
+        }
+        match cmd {
+            Command::Action(action) => self.handle_action(backend, action),
+            Command::Unbound(key) => {
+                self.status = Some(format!("{} does nothing, ? lists the keys", key));
+                Outcome::Nothing
+            },
+            Command::Click(_) | Command::Ignore => Outcome::Nothing,
+        }
+    }
+
+    pub fn select(&mut self, idx: usize) -> Outcome {
+        if idx < self.images.len() {
+            self.current = idx;
+            Outcome::Redraw
+        } else {
+            Outcome::Nothing
+        }
+    }
+
+    fn handle_action(&mut self, backend: &dyn MachineBackend, action: Action) -> Outcome {
+        let running = self.selected().is_some_and(|i| i.machine.is_some());
+        if let Some(img) = self.selected().filter(|i| i.source.is_some()) {
+            match action {
+                Action::Clone | Action::Rename | Action::Remove | Action::ReadOnly | Action::Limit | Action::Autostart | Action::Settings | Action::StartWith | Action::Ephemeral => {
+                    self.status = Some(format!("{} is a snapshot of {}", img.name, img.source.as_ref().unwrap()));
+                    return Outcome::Redraw;
+                },
+                _ => {},
+            }
+        }
+        match action {
+            Action::Shell => {
+                if running {
+                    let name = self.images[self.current].name.clone();
+                    let user = self.config.shell_users.get(&name).cloned().unwrap_or_else(config::invoking_user);
+                    let form = Form::new(&format!("Shell in {}", name)).text("User", &user);
+                    self.popup = Some(Popup::Form(FormKind::Shell(name), form));
+                    Outcome::Redraw
+                } else {
+                    Outcome::Nothing
+                }
+            }
+            Action::Reboot => {
+                if running {
+                    let name = self.images[self.current].name.clone();
+                    if let Err(e) = backend.reboot_machine(&name) {
+                        self.fail(format!("Rebooting {} failed", name), e);
+                        return Outcome::Redraw;
+                    }
+                }
+                Outcome::Nothing
+            }
+            Action::Info => {
+                match self.selected().and_then(|i| i.machine.clone()) {
+                    Some(m) => {
+                        let details = backend.machine_details(&m);
+                        self.popup = Some(Popup::MachineInfo(m.name, details));
+                        Outcome::Redraw
+                    },
+                    None => {
+                        self.status = Some("machine is not running".to_string());
+                        Outcome::Nothing
+                    },
+                }
+            },
+            Action::StartStop => {
+                if !self.images.is_empty() && !self.pending.contains_key(&self.images[self.current].name) {
+                    let name = self.images[self.current].name.clone();
+                    if running {
+                        match backend.poweroff_machine(&name) {
+                            Ok(()) => { self.pending.insert(name, Pending::Stopping(Instant::now() + STOP_TIMEOUT)); },
+                            Err(e) => self.fail(format!("Powering off {} failed", name), e),
+                        }
+                    } else {
+                        match backend.start_machine(&name) {
+                            Ok(job) => { self.pending.insert(name, Pending::Starting(job)); },
+                            Err(e) => self.fail(format!("Starting {} failed", name), e),
+                        }
+                    }
+                    Outcome::Redraw
+                } else {
+                    Outcome::Nothing
+                }
+            },
+            Action::StartWith => {
+                let img = match self.selected() {
+                    Some(img) if !self.pending.contains_key(&img.name) => img,
+                    _ => return Outcome::Nothing,
+                };
+                if img.machine.is_some() {
+                    self.status = Some(format!("{} is already running", img.name));
+                    return Outcome::Redraw;
+                }
+                // read-only images are usually meant to be run on a snapshot
+                let binds: Vec<String> = self.config.binds.get(&img.name).map_or(Vec::new(), |b| b.iter().map(|b| b.spec()).collect());
+                let form = Form::new(&format!("Start {} with options", img.name))
+                    .toggle("Ephemeral", img.ro)
+                    .toggle("Read-only", false)
+                    .text("Bind", &binds.join(" "))
+                    .text("Zone", "")
+                    .text("Boot target", "");
+                self.popup = Some(Popup::Form(FormKind::Start(img.name.clone()), form));
+                Outcome::Redraw
+            },
+            // nspawn removes the snapshot itself when the machine stops, mat
+            // doesn't have to be around for that
+            Action::Ephemeral => {
+                let img = match self.selected() {
+                    Some(img) => img.clone(),
+                    None => return Outcome::Nothing,
+                };
+                if img.t != "directory" && img.t != "subvolume" {
+                    self.status = Some(format!("{}: only directory images can be run on a snapshot", img.name));
+                    return Outcome::Redraw;
+                }
+                let machine = (1..).map(|n| ephemeral_name(&img.name, n)).find(|m| !self.images.iter().any(|i| &i.name == m) && !self.pending.contains_key(m)).unwrap();
+                if let Err(e) = check_image_name(&machine) {
+                    self.status = Some(format!("{}: {}", machine, e));
+                    return Outcome::Redraw;
+                }
+                let started = backend.image_path(&img.name).and_then(|path| {
+                    let options = StartOptions { ephemeral: true, directory: Some(path.clone()), source: Some(img.name.clone()), ..Default::default() };
+                    backend.start_machine_with(&machine, &options).map(|job| (job, path))
+                });
+                match started {
+                    Ok((job, path)) => {
+                        // nspawn looks for settings named after the machine
+                        self.status = Some(match nspawn::locate(&self.nspawn_dirs, Some(Path::new(&path)), &img.name) {
+                            Some(file) => format!("{}: starting {} on a snapshot, without the settings in {}", machine, img.name, file.display()),
+                            None => format!("{}: starting {} on a snapshot", machine, img.name),
+                        });
+                        self.pending.insert(machine.clone(), Pending::Starting(job));
+                        self.want_selected = Some(machine);
+                    },
+                    Err(e) => self.fail(format!("Starting {} on a snapshot failed", img.name), e),
+                }
+                Outcome::Redraw
+            },
+            Action::Import => {
+                let cwd = env::current_dir().map(|d| format!("{}/", d.display())).unwrap_or_default();
+                let form = Form::new("Import an image")
+                    .path("File or directory", &cwd)
+                    .choice("Format", &["auto", "tar", "raw", "directory"], 0)
+                    .text("Name, empty for the file's", "")
+                    .toggle("Read-only", false)
+                    .toggle("Replace existing", false);
+                self.popup = Some(Popup::Form(FormKind::Import, form));
+                Outcome::Redraw
+            },
+            Action::Clone => {
+                if let Some(img) = self.selected() {
+                    let form = Form::new(&format!("Clone {}", img.name))
+                        .text("Name", &self.clone_name(&img.name))
+                        .toggle("Read-only", img.ro);
+                    self.popup = Some(Popup::Form(FormKind::Clone(img.name.clone()), form));
+                    return Outcome::Redraw;
+                }
+                Outcome::Nothing
+            },
+            Action::Rename | Action::Remove => {
+                let img = match self.selected() {
+                    Some(img) => img.clone(),
+                    None => return Outcome::Nothing,
+                };
+                if img.machine.is_some() {
+                    self.status = Some(format!("{} is running, stop it first", img.name));
+                    return Outcome::Nothing;
+                }
+                self.popup = Some(if action == Action::Rename {
+                    Popup::Form(FormKind::Rename(img.name.clone()), Form::new(&format!("Rename {}", img.name)).text("Name", &img.name))
+                } else {
+                    let question = format!("Remove {} ({}, {})? This can not be undone.", img.name, format_size(img.size), if img.ro { "read-only" } else { "writable" });
+                    Popup::Confirm(Confirm::Remove(img.name), question)
+                });
+                Outcome::Redraw
+            },
+            Action::ReadOnly => {
+                let (name, ro) = match self.selected() {
+                    Some(img) => (img.name.clone(), !img.ro),
+                    None => return Outcome::Nothing,
+                };
+                match backend.mark_image_read_only(&name, ro) {
+                    Ok(()) => {
+                        self.status = Some(format!("{}: {}", name, if ro { "read-only" } else { "writable" }));
+                        Outcome::Update
+                    },
+                    Err(e) => {
+                        self.fail(format!("Changing {} failed", name), e);
+                        Outcome::Redraw
+                    },
+                }
+            },
+            Action::Limit => {
+                let name = match self.selected() {
+                    Some(img) => img.name.clone(),
+                    None => return Outcome::Nothing,
+                };
+                let limit = match self.selected_details() {
+                    Some(&Ok(ImageDetails { limit: Some(l), .. })) => format_size(l),
+                    _ => String::new(),
+                };
+                let form = Form::new(&format!("Limit {}", name)).text("Size, e.g. 20G", &limit);
+                self.popup = Some(Popup::Form(FormKind::Limit(name), form));
+                Outcome::Redraw
+            },
+            Action::PoolLimit => {
+                let limit = self.pool.as_ref().and_then(|p| p.limit).map(format_size).unwrap_or_default();
+                self.popup = Some(Popup::Form(FormKind::PoolLimit, Form::new("Pool limit").text("Size, e.g. 20G", &limit)));
+                Outcome::Redraw
+            },
+            Action::CleanPool => {
+                let form = Form::new("Clean pool").choice("Remove", &["hidden", "all"], 0);
+                self.popup = Some(Popup::Form(FormKind::CleanPool, form));
+                Outcome::Redraw
+            },
+            Action::Console => {
+                if running {
+                    let name = self.images[self.current].name.clone();
+                    let form = Form::new(&format!("Terminal in {}", name)).choice("Type", &["login", "pty"], 0);
+                    self.popup = Some(Popup::Form(FormKind::Console(name), form));
+                    Outcome::Redraw
+                } else {
+                    self.status = Some("machine is not running".to_string());
+                    Outcome::Nothing
+                }
+            },
+            Action::Copy => {
+                if running {
+                    let name = self.images[self.current].name.clone();
+                    let cwd = env::current_dir().map(|d| format!("{}/", d.display().to_string().trim_end_matches('/'))).unwrap_or_default();
+                    let form = Form::new(&format!("Copy files, {}", name))
+                        .choice("Direction", &["to machine", "from machine"], 0)
+                        .path("Host path", &cwd)
+                        .path("Machine path", "/")
+                        .toggle("Replace", false);
+                    self.popup = Some(Popup::Form(FormKind::Copy(name), form));
+                    Outcome::Redraw
+                } else {
+                    self.status = Some("machine is not running".to_string());
+                    Outcome::Nothing
+                }
+            },
+            Action::Bind => {
+                if running {
+                    let name = self.images[self.current].name.clone();
+                    let cwd = env::current_dir().map(|d| d.display().to_string()).unwrap_or_default();
+                    let mut form = Form::new(&format!("Bind mount into {}", name))
+                        .path("Host directory", &cwd)
+                        .path("Machine directory", "")
+                        .toggle("Read-only", false)
+                        .toggle("Create directory", true)
+                        .toggle("Remember", true);
+                    form.message = Some("an empty machine directory is the host directory, like --bind=SRC".to_string());
+                    self.popup = Some(Popup::Form(FormKind::Bind(name), form));
+                    Outcome::Redraw
+                } else {
+                    self.status = Some("machine is not running".to_string());
+                    Outcome::Nothing
+                }
+            },
+            Action::Rebind => {
+                if !running {
+                    self.status = Some("machine is not running".to_string());
+                    return Outcome::Nothing;
+                }
+                let name = self.images[self.current].name.clone();
+                let binds = self.config.binds.get(&name).cloned().unwrap_or_default();
+                if binds.is_empty() {
+                    self.status = Some(format!("no bind mounts saved for {}, b adds one", name));
+                    return Outcome::Nothing;
+                }
+                let mut text = String::new();
+                for b in binds {
+                    let result = match backend.bind_mount(&name, &b) {
+                        Ok(()) => "ok".to_string(),
+                        Err(e) => e.to_string().lines().next().unwrap_or("").to_string(),
+                    };
+                    text += &format!("{} → {}{}: {}\n", b.source, b.destination, if b.read_only { " (ro)" } else { "" }, result);
+                }
+                self.popup = Some(Popup::Message(format!("Bind mounts of {}", name), text));
+                Outcome::Redraw
+            },
+            Action::Kill | Action::Terminate => {
+                let name = match self.selected() {
+                    Some(img) if img.machine.is_some() => img.name.clone(),
+                    Some(_) => {
+                        self.status = Some("machine is not running".to_string());
+                        return Outcome::Redraw;
+                    },
+                    None => return Outcome::Nothing,
+                };
+                self.popup = Some(if action == Action::Kill {
+                    let signals: Vec<&str> = SIGNALS.iter().map(|s| s.0).collect();
+                    let form = Form::new(&format!("Send a signal to {}", name))
+                        .choice("Who", &["leader", "all"], 0)
+                        .choice("Signal", &signals, 0)
+                        .toggle("Terminate if still running", false)
+                        .text("after seconds", &STOP_TIMEOUT.as_secs().to_string());
+                    Popup::Form(FormKind::Kill(name), form)
+                } else {
+                    let question = format!("Terminate {}? All its processes get killed right away.", name);
+                    Popup::Confirm(Confirm::Terminate(name), question)
+                });
+                Outcome::Redraw
+            },
+            Action::Sort => {
+                self.sort = self.sort.next();
+                self.resort();
+                self.status = Some(format!("sorted by {}", self.sort.label()));
+                Outcome::Redraw
+            },
+            Action::Settings => {
+                let name = match self.selected() {
+                    Some(img) => img.name.clone(),
+                    None => return Outcome::Nothing,
+                };
+                match self.load_settings(backend, &name) {
+                    Ok((source, settings)) => {
+                        let mut form = settings_form(&name, &settings);
+                        match source {
+                            Some(ref s) if s.starts_with(&self.nspawn_dirs[0]) => {},
+                            Some(ref s) if self.nspawn_dirs.iter().any(|d| s.starts_with(d)) => {
+                                form.message = Some(format!("from {}, saved to {}", s.display(), self.nspawn_dirs[0].display()));
+                            },
+                            Some(ref s) => {
+                                form.message = Some(format!("from {}, only changes are saved to {}", s.display(), self.nspawn_dirs[0].display()));
+                            },
+                            None => {},
+                        }
+                        self.popup = Some(Popup::Form(FormKind::Settings(name), form));
+                    },
+                    Err(e) => self.fail(format!("Reading the settings of {} failed", name), e),
+                }
+                Outcome::Redraw
+            },
+            Action::Log => {
+                if self.log.take().is_some() {
+                    return Outcome::Redraw;
+                }
+                match self.selected().map(|i| i.name.clone()) {
+                    Some(name) => self.open_log(backend, &name, false),
+                    None => Outcome::Nothing,
+                }
+            },
+            Action::Processes => {
+                if self.processes.take().is_some() {
+                    return Outcome::Redraw;
+                }
+                let machine = match self.selected().and_then(|i| i.machine.clone()) {
+                    Some(m) => m,
+                    None => {
+                        self.status = Some("machine is not running".to_string());
+                        return Outcome::Redraw;
+                    },
+                };
+                let listed = backend.machine_details(&machine).and_then(|d| Ok((backend.unit_processes(&d.unit)?, d.unit)));
+                match listed {
+                    Ok((procs, unit)) => {
+                        self.processes = Some(ProcessView::new(&machine.name, &unit, &procs, Instant::now()));
+                        self.log = None;
+                    },
+                    Err(e) => self.fail(format!("Listing processes of {} failed", machine.name), e),
+                }
+                Outcome::Redraw
+            },
+            Action::Freeze => {
+                let (name, frozen) = match self.selected() {
+                    Some(img) if img.machine.is_some() => (img.name.clone(), img.frozen),
+                    Some(_) => {
+                        self.status = Some("machine is not running".to_string());
+                        return Outcome::Redraw;
+                    },
+                    None => return Outcome::Nothing,
+                };
+                let r = if frozen { backend.thaw_machine(&name) } else { backend.freeze_machine(&name) };
+                match r {
+                    Ok(()) => {
+                        // confirmed by the next sample
+                        self.images[self.current].frozen = !frozen;
+                        self.usage_due = true;
+                        self.status = Some(format!("{} {}", name, if frozen { "resumed" } else { "paused" }));
+                        Outcome::Redraw
+                    },
+                    Err(e) => {
+                        self.fail(format!("{} {} failed", if frozen { "Thawing" } else { "Freezing" }, name), e);
+                        Outcome::Redraw
+                    },
+                }
+            },
+            Action::Autostart => {
+                let (name, enable) = match self.selected() {
+                    Some(img) => (img.name.clone(), !img.autostart),
+                    None => return Outcome::Nothing,
+                };
+                match backend.set_autostart(&name, enable) {
+                    Ok(()) => {
+                        self.autostart = None;
+                        self.status = Some(format!("{}: {}", name, if enable { "starts at boot" } else { "no longer starts at boot" }));
+                        Outcome::Update
+                    },
+                    Err(e) => {
+                        self.fail(format!("Changing autostart of {} failed", name), e);
+                        Outcome::Redraw
+                    },
+                }
+            },
+            Action::NextTab => {
+                if self.consoles.is_empty() {
+                    self.status = Some("no terminals open, t opens one".to_string());
+                    return Outcome::Nothing;
+                }
+                self.next_tab()
+            },
+            // only meaningful in a console
+            Action::CloseTab => Outcome::Nothing,
+            Action::Refresh => {
+                self.current = 0;
+                self.details.clear();
+                self.snapshots_checked = false;
+                Outcome::Update
+            },
+            Action::Help => {
+                self.popup = Some(Popup::Message("Keys".to_string(), keymap::help_table()));
+                Outcome::Redraw
+            },
+            Action::Quit => Outcome::Quit,
+            Action::Up => {
+                if self.current > 0 {
+                    self.current -= 1;
+                }
+                Outcome::Redraw
+            },
+            Action::Down => {
+                if self.current + 1 < self.images.len() {
+                    self.current += 1;
+                }
+                Outcome::Redraw
+            },
+        }
+    }
+
+    pub fn handle_bus(&mut self, ev: BusEvent) -> Outcome {
+        match ev {
+            BusEvent::MachineNew(_) => {},
+            BusEvent::MachineRemoved(name) => {
+                if self.processes.as_ref().is_some_and(|v| v.name == name) {
+                    self.processes = None;
+                }
+                match self.pending.get(&name) {
+                    Some(&Pending::Stopping(_)) | Some(&Pending::Killing(_)) => {
+                        self.pending.remove(&name);
+                        self.status = Some(format!("{}: stopped", name));
+                    },
+                    _ => {},
+                }
+            },
+            BusEvent::JobRemoved { job, unit, result } => {
+                let name = self.pending.iter().find(|&(_, p)| *p == Pending::Starting(job.clone())).map(|(n, _)| n.clone());
+                match name {
+                    Some(name) => {
+                        self.pending.remove(&name);
+                        self.status = Some(format!("{}: {}", name, job_result(&result)));
+                    },
+                    // not one of ours
+                    None => {
+                        if !self.images.iter().any(|i| unit_name(&i.name) == unit || transient_unit_name(&i.name) == unit) {
+                            return Outcome::Nothing;
+                        }
+                    },
+                }
+            },
+            BusEvent::UnitFilesChanged => self.autostart = None,
+            // the nspawn of a run has exited for good, MachineRemoved may come
+            // before it has removed its snapshot
+            BusEvent::UnitRemoved(unit) => {
+                if transient_machine(&unit).is_none() {
+                    return Outcome::Nothing;
+                }
+                self.snapshots_checked = false;
+            },
+            BusEvent::TransferNew(id) => {
+                if self.transfers.iter().any(|t| t.id == id) {
+                    return Outcome::Nothing;
+                }
+                // looked up with the next update
+                self.unknown_transfers = true;
+            },
+            BusEvent::TransferRemoved { id, result } => {
+                if let Some(idx) = self.transfers.iter().position(|t| t.id == id) {
+                    let t = self.transfers.remove(idx);
+                    let done = match t.kind.split('-').next().unwrap_or("") {
+                        "export" => "exported",
+                        "pull" => "downloaded",
+                        _ => "imported",
+                    };
+                    self.status = Some(match result.as_str() {
+                        "done" => format!("{}: {}", t.local, done),
+                        "canceled" => format!("{}: canceled", t.local),
+                        r => format!("{}: {} {}", t.local, t.kind.split('-').next().unwrap_or("transfer"), r),
+                    });
+                    if result == "done" && done != "exported" {
+                        self.want_selected = Some(t.local);
+                    }
+                }
+            },
+            BusEvent::TaskDone(task, result) => {
+                if let Some(idx) = self.tasks.iter().position(|t| *t == task) {
+                    self.tasks.remove(idx);
+                }
+                match result {
+                    Ok(TaskOutput::Removed(removed)) if matches!(task, Task::RemoveSnapshots { .. }) => {
+                        let reclaimed = format_size(removed.iter().map(|r| r.1).sum());
+                        self.status = Some(format!("{} stale snapshots removed, {} reclaimed", removed.len(), if reclaimed.is_empty() { "0" } else { &reclaimed }));
+                    },
+                    Ok(TaskOutput::Removed(removed)) => {
+                        let total: u64 = removed.iter().map(|r| r.1).sum();
+                        let reclaimed = format_size(total);
+                        let mut text = format!("{} images removed, {} reclaimed\n", removed.len(), if reclaimed.is_empty() { "0" } else { &reclaimed });
+                        for (name, size) in removed {
+                            text += &format!("\n  {} {}", name, format_size(size));
+                        }
+                        self.popup = Some(Popup::Message("Pool cleaned".to_string(), text));
+                    },
+                    Ok(TaskOutput::Nothing) => {
+                        self.status = Some(format!("{}: done", task.describe()));
+                        if let Task::Clone { new_name, .. } = task {
+                            self.want_selected = Some(new_name);
+                        }
+                    },
+                    Err(e) => self.fail(format!("{} failed", task.describe()), e),
+                }
+            },
+        }
+        Outcome::Update
+    }
+
+    // animate the progress of tasks, sample resource usage, reload the process and log panes,
+    // give up on containers that don't shut down and terminate those that ignored a signal for too long
+    pub fn tick(&mut self, backend: &dyn MachineBackend, now: Instant) -> Outcome {
+        let mut outcome = Outcome::Nothing;
+        if !self.tasks.is_empty() {
+            self.spinner = self.spinner.wrapping_add(1);
+            outcome = Outcome::Redraw;
+        }
+        if (self.usage_due || self.usage_loaded + USAGE_REFRESH <= now) && self.images.iter().any(|i| i.machine.is_some()) {
+            self.load_usage(backend, now);
+            self.resort();
+            outcome = Outcome::Redraw;
+        }
+        if let Some(ref mut view) = self.log {
+            if view.loaded + LOG_REFRESH <= now && view.reload(now) {
+                outcome = Outcome::Redraw;
+            }
+        }
+        if self.processes.as_ref().is_some_and(|v| v.loaded + PROCESS_REFRESH <= now) {
+            self.load_processes(backend, now);
+            outcome = Outcome::Redraw;
+        }
+        if !self.transfers.is_empty() && self.transfers_loaded + TRANSFER_REFRESH <= now {
+            self.load_transfers(backend, now);
+            outcome = Outcome::Redraw;
+        }
+        let expired: Vec<String> = self.pending.iter().filter_map(|(n, p)| match *p {
+            Pending::Stopping(deadline) | Pending::Killing(deadline) if deadline <= now => Some(n.clone()),
+            _ => None,
+        }).collect();
+        if expired.is_empty() {
+            return outcome;
+        }
+        for name in expired {
+            match self.pending.remove(&name) {
+                Some(Pending::Killing(_)) => match backend.terminate_machine(&name) {
+                    Ok(()) => {
+                        self.pending.insert(name.clone(), Pending::Stopping(now + STOP_TIMEOUT));
+                        self.status = Some(format!("{}: still running, terminated", name));
+                    },
+                    Err(e) => self.fail(format!("Terminating {} failed", name), e),
+                },
+                _ => self.status = Some(format!("{}: still running after {}s", name, STOP_TIMEOUT.as_secs())),
+            }
+        }
+        Outcome::Update
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use backend::{Usage,SIGRTMIN_4};
+    use backend::fake::FakeBackend;
+    use journal::tests::write_journal;
+    use testdir::TempDir;
+    use notcurses::{Received,Key,KeyMod,InputType};
+
+    fn press(received: Received) -> Input {
+        Input { received, keymod: KeyMod::None, itype: InputType::Press, cell: None, offset: None }
+    }
+
+    fn app_with(fake: &FakeBackend) -> App {
+        let mut app = App::new();
+        app.update(fake).unwrap();
+        // the first tick samples the running machines
+        app.tick(fake, Instant::now());
+        app
+    }
+
+    fn names(app: &App) -> Vec<&str> {
+        app.images.iter().map(|i| i.name.as_str()).collect()
+    }
+
+    #[test]
+    fn update_sorts_and_hides_dot_images() {
+        let fake = FakeBackend::with_images(&["tumbleweed", ".hidden", "leap"]);
+        let app = app_with(&fake);
+        assert_eq!(names(&app), vec!["leap", "tumbleweed"]);
+    }
+
+    #[test]
+    fn update_attaches_running_machines() {
+        let fake = FakeBackend::with_images(&["leap", "tumbleweed"]);
+        fake.run("tumbleweed");
+        let app = app_with(&fake);
+        assert!(app.images[0].machine.is_none());
+        assert_eq!(app.images[1].machine.as_ref().unwrap().name, "tumbleweed");
+    }
+
+    #[test]
+    fn update_keeps_selection() {
+        let fake = FakeBackend::with_images(&["b", "c"]);
+        let mut app = app_with(&fake);
+        app.current = 1;
+        fake.add_image("a", false, 0);
+        app.update(&fake).unwrap();
+        assert_eq!(app.selected().unwrap().name, "c");
+        fake.remove_image("c").unwrap();
+        app.update(&fake).unwrap();
+        assert_eq!(app.selected().unwrap().name, "b");
+    }
+
+    #[test]
+    fn cursor_stays_in_range() {
+        let fake = FakeBackend::with_images(&["a", "b"]);
+        let mut app = app_with(&fake);
+        assert_eq!(app.handle_input(&fake, &press(Received::Key(Key::Up))), Outcome::Redraw);
+        assert_eq!(app.current, 0);
+        app.handle_input(&fake, &press(Received::Key(Key::Down)));
+        app.handle_input(&fake, &press(Received::Key(Key::Down)));
+        assert_eq!(app.current, 1);
+    }
+
+    #[test]
+    fn enter_starts_stopped_machine() {
+        let fake = FakeBackend::with_images(&["leap"]);
+        let mut app = app_with(&fake);
+        assert_eq!(app.handle_input(&fake, &press(Received::Key(Key::Enter))), Outcome::Redraw);
+        assert_eq!(fake.calls(), vec!["start leap"]);
+        assert!(matches!(app.pending.get("leap"), Some(&Pending::Starting(_))));
+        // no second start while the job is running
+        assert_eq!(app.handle_input(&fake, &press(Received::Key(Key::Enter))), Outcome::Nothing);
+        assert_eq!(fake.calls().len(), 1);
+    }
+
+    #[test]
+    fn job_removed_reports_result() {
+        let fake = FakeBackend::with_images(&["leap"]);
+        let mut app = app_with(&fake);
+        app.handle_input(&fake, &press(Received::Key(Key::Enter)));
+        let job = match app.pending.get("leap") { Some(Pending::Starting(job)) => job.clone(), _ => panic!() };
+        let ev = BusEvent::JobRemoved { job, unit: unit_name("leap"), result: "failed".to_string() };
+        assert_eq!(app.handle_bus(ev), Outcome::Update);
+        assert!(app.pending.is_empty());
+        assert_eq!(app.status.as_ref().unwrap(), "leap: failed to start");
+    }
+
+    #[test]
+    fn foreign_jobs_are_ignored() {
+        let fake = FakeBackend::with_images(&["leap"]);
+        let mut app = app_with(&fake);
+        let job = dbus::Path::new("/org/freedesktop/systemd1/job/4711").unwrap();
+        let ev = BusEvent::JobRemoved { job, unit: "foo.service".to_string(), result: "done".to_string() };
+        assert_eq!(app.handle_bus(ev), Outcome::Nothing);
+        assert!(app.status.is_none());
+    }
+
+    #[test]
+    fn enter_powers_off_running_machine() {
+        let fake = FakeBackend::with_images(&["leap"]);
+        fake.run("leap");
+        let mut app = app_with(&fake);
+        assert_eq!(app.handle_input(&fake, &press(Received::Key(Key::Enter))), Outcome::Redraw);
+        assert_eq!(fake.calls(), vec!["kill leap leader 38"]);
+        fake.stop("leap");
+        assert_eq!(app.handle_bus(BusEvent::MachineRemoved("leap".to_string())), Outcome::Update);
+        assert!(app.pending.is_empty());
+        assert_eq!(app.status.as_ref().unwrap(), "leap: stopped");
+    }
+
+    #[test]
+    fn stopping_times_out() {
+        let fake = FakeBackend::with_images(&["leap"]);
+        fake.run("leap");
+        let mut app = app_with(&fake);
+        app.handle_input(&fake, &press(Received::Key(Key::Enter)));
+        assert_eq!(app.tick(&fake, Instant::now()), Outcome::Nothing);
+        assert_eq!(app.tick(&fake, Instant::now() + STOP_TIMEOUT), Outcome::Update);
+        assert!(app.pending.is_empty());
+        assert_eq!(app.status.as_ref().unwrap(), "leap: still running after 30s");
+    }
+
+    #[test]
+    fn kill_with_escalation() {
+        let fake = FakeBackend::with_images(&["leap"]);
+        fake.run("leap");
+        let mut app = app_with(&fake);
+        app.handle_input(&fake, &press(Received::Char('k')));
+        for key in &[Received::Key(Key::Down), Received::Key(Key::Right), Received::Key(Key::Right), Received::Key(Key::Down), Received::Char(' '),
+                     Received::Key(Key::Down), Received::Key(Key::Backspace), Received::Key(Key::Backspace), Received::Char('5'), Received::Key(Key::Enter)] {
+            app.handle_input(&fake, &press(*key));
+        }
+        assert!(app.popup.is_none());
+        assert_eq!(fake.calls(), vec!["kill leap leader 1"]);
+        assert_eq!(app.status.as_ref().unwrap(), "leap: SIGHUP sent to leader");
+        assert_eq!(app.tick(&fake, Instant::now()), Outcome::Nothing);
+        assert_eq!(app.tick(&fake, Instant::now() + Duration::from_secs(5)), Outcome::Update);
+        assert_eq!(fake.calls(), vec!["kill leap leader 1", "terminate leap"]);
+        assert_eq!(app.status.as_ref().unwrap(), "leap: still running, terminated");
+        fake.stop("leap");
+        app.handle_bus(BusEvent::MachineRemoved("leap".to_string()));
+        assert!(app.pending.is_empty());
+    }
+
+    #[test]
+    fn kill_rejects_bad_timeout() {
+        let fake = FakeBackend::with_images(&["leap"]);
+        fake.run("leap");
+        let mut app = app_with(&fake);
+        app.handle_input(&fake, &press(Received::Char('k')));
+        for key in &[Received::Key(Key::Down), Received::Key(Key::Down), Received::Char(' '), Received::Key(Key::Down), Received::Char('x'), Received::Key(Key::Enter)] {
+            app.handle_input(&fake, &press(*key));
+        }
+        match app.popup {
+            Some(Popup::Form(FormKind::Kill(_), ref form)) => assert_eq!(form.message.as_ref().unwrap(), "'30x' is not a number of seconds"),
+            _ => panic!("kill form closed"),
+        }
+        assert!(fake.calls().is_empty());
+    }
+
+    #[test]
+    fn terminate_asks_first() {
+        let fake = FakeBackend::with_images(&["leap"]);
+        fake.run("leap");
+        let mut app = app_with(&fake);
+        app.handle_input(&fake, &press(Received::Char('K')));
+        assert!(fake.calls().is_empty());
+        app.handle_input(&fake, &press(Received::Char('y')));
+        assert_eq!(fake.calls(), vec!["terminate leap"]);
+        assert!(app.pending.contains_key("leap"));
+    }
+
+    #[test]
+    fn process_pane() {
+        let fake = FakeBackend::with_images(&["leap"]);
+        fake.run("leap");
+        fake.add_process("/machine.slice/systemd-nspawn@leap.service/payload", 101, "init");
+        fake.add_process("/machine.slice/systemd-nspawn@leap.service/supervisor", 100, "systemd-nspawn");
+        let mut app = app_with(&fake);
+        assert_eq!(app.handle_input(&fake, &press(Received::Char('P'))), Outcome::Redraw);
+        assert_eq!(app.processes.as_ref().unwrap().rows.len(), 5);
+        app.handle_input(&fake, &press(Received::Key(Key::Down)));
+        app.handle_input(&fake, &press(Received::Key(Key::Down)));
+        assert_eq!(app.current, 0);
+        app.handle_input(&fake, &press(Received::Char('k')));
+        app.handle_input(&fake, &press(Received::Key(Key::Right)));
+        app.handle_input(&fake, &press(Received::Key(Key::Enter)));
+        assert_eq!(fake.calls(), vec!["machine leap", "kill-pid 101 2"]);
+        assert_eq!(app.status.as_ref().unwrap(), "SIGINT sent to 101");
+
+        fake.add_process("/machine.slice/systemd-nspawn@leap.service/payload", 102, "sh");
+        assert_eq!(app.tick(&fake, Instant::now()), Outcome::Nothing);
+        assert_eq!(app.tick(&fake, Instant::now() + PROCESS_REFRESH), Outcome::Redraw);
+        assert_eq!(app.processes.as_ref().unwrap().rows.len(), 6);
+        app.handle_input(&fake, &press(Received::Key(Key::Esc)));
+        assert!(app.processes.is_none());
+    }
+
+    #[test]
+    fn usage_and_sorting() {
+        let fake = FakeBackend::with_images(&["leap", "sles", "tumbleweed"]);
+        fake.run("sles");
+        fake.run("tumbleweed");
+        let usage = |cpu: u64, memory: u64| Usage { memory: Some(memory), cpu: Some(cpu), tasks: Some(10), io: None, frozen: false };
+        fake.usage.borrow_mut().insert(unit_name("sles"), usage(0, 1<<20));
+        fake.usage.borrow_mut().insert(unit_name("tumbleweed"), usage(0, 1<<30));
+        let mut app = app_with(&fake);
+        assert!(app.images[0].usage.is_none());
+        assert_eq!(app.images[1].usage.unwrap().memory, Some(1<<20));
+        assert!(app.images[1].cpu_percent.is_none());
+
+        let start = app.usage_loaded;
+        fake.usage.borrow_mut().insert(unit_name("sles"), usage(2_500_000_000, 1<<20));
+        fake.usage.borrow_mut().insert(unit_name("tumbleweed"), usage(500_000_000, 1<<30));
+        assert_eq!(app.tick(&fake, start + Duration::from_secs(4)), Outcome::Nothing);
+        assert_eq!(app.tick(&fake, start + USAGE_REFRESH), Outcome::Redraw);
+        assert_eq!(app.images[1].cpu_percent, Some(50.0));
+        assert_eq!(app.images[2].cpu_percent, Some(10.0));
+        // kept over list updates
+        app.update(&fake).unwrap();
+        assert_eq!(app.images[1].cpu_percent, Some(50.0));
+
+        app.handle_input(&fake, &press(Received::Char('s')));
+        assert_eq!(app.sort, Sort::Size);
+        app.handle_input(&fake, &press(Received::Char('s')));
+        assert_eq!(names(&app), vec!["sles", "tumbleweed", "leap"]);
+        assert_eq!(app.current, 2);
+        app.handle_input(&fake, &press(Received::Char('s')));
+        assert_eq!(names(&app), vec!["tumbleweed", "sles", "leap"]);
+        assert_eq!(app.status.as_ref().unwrap(), "sorted by memory");
+
+        // sampled on the next tick, not while updating the list
+        fake.run("leap");
+        app.update(&fake).unwrap();
+        assert!(app.usage_due);
+        assert!(app.images[2].usage.is_none());
+        assert_eq!(app.tick(&fake, Instant::now()), Outcome::Redraw);
+        assert!(app.images[2].usage.is_some());
+    }
+
+    #[test]
+    fn log_pane() {
+        let dir = TempDir::new("log-pane");
+        fs::create_dir_all(dir.join("host")).unwrap();
+        fs::create_dir_all(dir.join("0123456789abcdef0123456789abcdef")).unwrap();
+        write_journal(&dir.join("host/system.journal"), true, &[
+            (1, &["MESSAGE=Starting leap", "UNIT=systemd-nspawn@leap.service"]),
+            (2, &["MESSAGE=unrelated", "_SYSTEMD_UNIT=sshd.service"]),
+            (3, &["MESSAGE=Failed to start leap", "PRIORITY=3", "UNIT=systemd-nspawn@leap.service"]),
+        ]);
+        write_journal(&dir.join("0123456789abcdef0123456789abcdef/system.journal"), false, &[(4, &["MESSAGE=inside", "SYSLOG_IDENTIFIER=sshd"])]);
+        let fake = FakeBackend::with_images(&["leap"]);
+        fake.run("leap");
+        let mut app = app_with(&fake);
+        app.journal_roots = vec![dir.clone()];
+        app.host_id = "host".to_string();
+
+        assert_eq!(app.handle_input(&fake, &press(Received::Char('j'))), Outcome::Redraw);
+        assert_eq!(app.log.as_ref().unwrap().lines.len(), 2);
+        app.handle_input(&fake, &press(Received::Char('3')));
+        assert_eq!(app.log.as_ref().unwrap().lines.len(), 1);
+        app.handle_input(&fake, &press(Received::Char('7')));
+        app.handle_input(&fake, &press(Received::Char('/')));
+        for c in "starting".chars() {
+            app.handle_input(&fake, &press(Received::Char(c)));
+        }
+        app.handle_input(&fake, &press(Received::Key(Key::Enter)));
+        assert!(app.popup.is_none());
+        assert_eq!(app.log.as_ref().unwrap().current, 0);
+        assert!(!app.log.as_ref().unwrap().follow);
+
+        app.handle_input(&fake, &press(Received::Char('m')));
+        let log = app.log.as_ref().unwrap();
+        assert!(log.machine);
+        assert_eq!(log.lines[0].text, "sshd: inside");
+        app.handle_input(&fake, &press(Received::Char('j')));
+        assert!(app.log.is_none());
+    }
+
+    #[test]
+    fn start_with_options() {
+        let fake = FakeBackend::with_images(&["golden"]);
+        fake.images.borrow_mut()[0].ro = true;
+        let mut app = app_with(&fake);
+        app.config.binds.insert("golden".to_string(), vec![BindMount::parse("/:/host:ro").unwrap()]);
+        assert_eq!(app.handle_input(&fake, &press(Received::Char('S'))), Outcome::Redraw);
+        match app.popup {
+            Some(Popup::Form(FormKind::Start(_), ref form)) => {
+                assert!(form.get_toggle(0));
+                assert_eq!(form.get_text(2), "/:/host:ro");
+            },
+            _ => panic!("no start form"),
+        }
+        for _ in 0..3 {
+            app.handle_input(&fake, &press(Received::Key(Key::Down)));
+        }
+        for c in "a zone".chars() {
+            app.handle_input(&fake, &press(Received::Char(c)));
+        }
+        app.handle_input(&fake, &press(Received::Key(Key::Enter)));
+        match app.popup {
+            Some(Popup::Form(_, ref form)) => assert_eq!(form.message.as_ref().unwrap(), "'a zone' is not a zone name of up to 12 letters and digits"),
+            _ => panic!("form closed"),
+        }
+        for _ in 0..6 {
+            app.handle_input(&fake, &press(Received::Key(Key::Backspace)));
+        }
+        for c in "dev".chars() {
+            app.handle_input(&fake, &press(Received::Char(c)));
+        }
+        app.handle_input(&fake, &press(Received::Key(Key::Down)));
+        for c in "rescue.target".chars() {
+            app.handle_input(&fake, &press(Received::Char(c)));
+        }
+        app.handle_input(&fake, &press(Received::Key(Key::Enter)));
+        assert!(app.popup.is_none());
+        assert_eq!(fake.calls(), vec!["start golden --ephemeral --bind-ro=/:/host --network-zone=dev systemd.unit=rescue.target"]);
+        let job = match app.pending.get("golden") { Some(Pending::Starting(job)) => job.clone(), _ => panic!() };
+        assert_eq!(app.handle_input(&fake, &press(Received::Char('S'))), Outcome::Nothing);
+        // the machine's own unit is used from now on
+        assert_eq!(fake.machine_unit("golden"), transient_unit_name("golden"));
+        let ev = BusEvent::JobRemoved { job, unit: transient_unit_name("golden"), result: "done".to_string() };
+        assert_eq!(app.handle_bus(ev), Outcome::Update);
+        app.update(&fake).unwrap();
+        assert_eq!(app.handle_input(&fake, &press(Received::Char('S'))), Outcome::Redraw);
+        assert_eq!(app.status.as_ref().unwrap(), "golden is already running");
+    }
+
+    #[test]
+    fn ephemeral_runs() {
+        let fake = FakeBackend::with_images(&["golden", "aaa", "tumbleweed"]);
+        fake.images.borrow_mut()[0].ro = true;
+        let mut app = app_with(&fake);
+        let dir = TempDir::new("ephemeral");
+        app.nspawn_dirs = vec![dir.clone()];
+        app.select(1);
+        assert_eq!(app.selected().unwrap().name, "golden");
+        assert_eq!(app.handle_input(&fake, &press(Received::Char('E'))), Outcome::Redraw);
+        assert_eq!(fake.calls(), vec!["start golden.eph1 --directory=/var/lib/machines/golden --ephemeral"]);
+        assert_eq!(app.status.as_ref().unwrap(), "golden.eph1: starting golden on a snapshot");
+        // a second one while the first is still starting
+        fs::write(dir.join("golden.nspawn"), "[Exec]\nBoot=yes\n").unwrap();
+        app.handle_input(&fake, &press(Received::Char('E')));
+        assert_eq!(fake.calls()[1], "start golden.eph2 --directory=/var/lib/machines/golden --ephemeral");
+        assert_eq!(app.status.as_ref().unwrap(), &format!("golden.eph2: starting golden on a snapshot, without the settings in {}/golden.nspawn", dir.display()));
+        // started without mat, it isn't known as a run of aaa
+        fake.run("aaa.eph1");
+        fake.add_image(".#machine.golden0123456789abcdef", false, 1 << 30);
+        fake.add_image(".#machine.tumbleweed0123456789abcdef", false, 2 << 30);
+        // after a restart of mat they are found again
+        let mut app = app_with(&fake);
+        assert_eq!(names(&app), vec!["aaa", "golden", "golden.eph1", "golden.eph2", "tumbleweed"]);
+        // and the snapshot left over from a run of tumbleweed is removed
+        assert_eq!(fake.calls().last().unwrap(), "remove .#machine.tumbleweed0123456789abcdef");
+        for ev in fake.take_events() {
+            app.handle_bus(ev);
+        }
+        assert_eq!(app.status.as_ref().unwrap(), "1 stale snapshots removed, 2G reclaimed");
+        assert!(app.tasks.is_empty());
+        app.update(&fake).unwrap();
+        assert_eq!(fake.calls().iter().filter(|c| c.starts_with("remove")).count(), 1);
+        let run = &app.images[2];
+        assert_eq!(run.source.as_ref().unwrap(), "golden");
+        assert!(run.machine.is_some() && !run.ro);
+        app.sort = Sort::Size;
+        fake.images.borrow_mut()[0].size = 1<<40;
+        app.update(&fake).unwrap();
+        assert_eq!(names(&app), vec!["golden", "golden.eph1", "golden.eph2", "aaa", "tumbleweed"]);
+
+        // it's a machine, but not an image
+        app.select(1);
+        assert_eq!(app.handle_input(&fake, &press(Received::Char('c'))), Outcome::Redraw);
+        assert!(app.popup.is_none());
+        assert_eq!(app.status.as_ref().unwrap(), "golden.eph1 is a snapshot of golden");
+        app.load_details(&fake);
+        assert!(app.selected_details().unwrap().is_ok());
+        app.handle_input(&fake, &press(Received::Key(Key::Enter)));
+        assert_eq!(fake.calls().last().unwrap(), &format!("kill golden.eph1 leader {}", SIGRTMIN_4));
+        fake.machines.borrow_mut().retain(|m| m.name != "golden.eph1");
+        app.update(&fake).unwrap();
+        assert_eq!(names(&app), vec!["golden", "golden.eph2", "aaa", "tumbleweed"]);
+
+        // the last run of golden dies without removing its snapshot
+        fake.stop("golden.eph2");
+        assert_eq!(app.handle_bus(BusEvent::MachineRemoved("golden.eph2".to_string())), Outcome::Update);
+        app.update(&fake).unwrap();
+        assert_eq!(fake.calls().iter().filter(|c| c.starts_with("remove")).count(), 1);
+        assert_eq!(app.handle_bus(BusEvent::UnitRemoved("foo.service".to_string())), Outcome::Nothing);
+        assert_eq!(app.handle_bus(BusEvent::UnitRemoved(transient_unit_name("golden.eph2"))), Outcome::Update);
+        app.update(&fake).unwrap();
+        assert_eq!(fake.calls().last().unwrap(), "remove .#machine.golden0123456789abcdef");
+    }
+
+    #[test]
+    fn import_dialog() {
+        let dir = TempDir::new("import");
+        fs::write(dir.join("leap.tar.xz"), "").unwrap();
+        let fake = FakeBackend::with_images(&["leap"]);
+        let mut app = app_with(&fake);
+        assert_eq!(app.handle_input(&fake, &press(Received::Char('I'))), Outcome::Redraw);
+        if let Some(Popup::Form(FormKind::Import, ref mut form)) = app.popup {
+            form.set_text(0, &dir.join("leap.tar.xz").to_string_lossy());
+        }
+        app.handle_input(&fake, &press(Received::Key(Key::Enter)));
+        match app.popup {
+            Some(Popup::Form(_, ref form)) => assert_eq!(form.message.as_ref().unwrap(), "leap already exists"),
+            _ => panic!("form closed"),
+        }
+        // as raw is wrong, but importd gets to tell
+        for key in &[Key::Down, Key::Right, Key::Right, Key::Down] {
+            app.handle_input(&fake, &press(Received::Key(*key)));
+        }
+        for c in "leap2".chars() {
+            app.handle_input(&fake, &press(Received::Char(c)));
+        }
+        app.handle_input(&fake, &press(Received::Key(Key::Down)));
+        app.handle_input(&fake, &press(Received::Char(' ')));
+        app.handle_input(&fake, &press(Received::Key(Key::Enter)));
+        assert!(app.popup.is_none());
+        assert_eq!(fake.calls(), vec![format!("import-raw {}/leap.tar.xz leap2 force=false ro=true", dir.display())]);
+        assert_eq!(app.transfers[0].describe(), "Importing leap2");
+
+        let now = Instant::now() + TRANSFER_REFRESH;
+        fake.transfers.borrow_mut()[0].progress = Some(0.42);
+        assert_eq!(app.tick(&fake, now), Outcome::Redraw);
+        assert_eq!(app.transfers[0].describe(), "Importing leap2 42%");
+        // someone else's
+        fake.import_image(ImportFormat::Directory, "/srv/tw", "tw", true, false).unwrap();
+        assert_eq!(app.handle_bus(BusEvent::TransferNew(1)), Outcome::Nothing);
+        assert_eq!(app.handle_bus(BusEvent::TransferNew(2)), Outcome::Update);
+        app.update(&fake).unwrap();
+        assert_eq!(app.transfers.len(), 2);
+        assert_eq!(app.transfers[1].local, "tw");
+
+        fake.add_image("leap2", true, 1<<30);
+        assert_eq!(app.handle_bus(BusEvent::TransferRemoved { id: 1, result: "done".to_string() }), Outcome::Update);
+        assert_eq!(app.status.as_ref().unwrap(), "leap2: imported");
+        app.update(&fake).unwrap();
+        assert_eq!(app.selected().unwrap().name, "leap2");
+        app.handle_bus(BusEvent::TransferRemoved { id: 2, result: "failed".to_string() });
+        assert_eq!(app.status.as_ref().unwrap(), "tw: import failed");
+        assert!(app.transfers.is_empty());
+    }
+
+    #[test]
+    fn settings_form() {
+        let dir = TempDir::new("settings-form");
+        let (etc, run) = (dir.join("etc"), dir.join("run"));
+        fs::create_dir_all(&run).unwrap();
+        fs::write(run.join("leap.nspawn"), "# from the package\n[Exec]\nBoot=true\nHostname=leap\n\n[Network]\nPort=2222:22\n").unwrap();
+        let fake = FakeBackend::with_images(&["leap"]);
+        let mut app = app_with(&fake);
+        app.nspawn_dirs = vec![etc.clone(), run.clone()];
+
+        assert_eq!(app.handle_input(&fake, &press(Received::Char('e'))), Outcome::Redraw);
+        match app.popup {
+            Some(Popup::Form(FormKind::Settings(ref name), ref form)) => {
+                assert_eq!(name, "leap");
+                assert_eq!(form.get_choice(0), "yes");
+                assert_eq!(form.get_choice(1), "default");
+                assert_eq!(form.get_text(4), "2222:22");
+                assert!(form.message.as_ref().unwrap().starts_with("from "));
+            },
+            _ => panic!("no settings form"),
+        }
+        // Boot stays, Bind gets two mounts, the zone a typo first
+        app.handle_input(&fake, &press(Received::Key(Key::Down)));
+        app.handle_input(&fake, &press(Received::Key(Key::Down)));
+        for c in "/srv:/srv:ro src".chars() {
+            app.handle_input(&fake, &press(Received::Char(c)));
+        }
+        app.handle_input(&fake, &press(Received::Key(Key::Enter)));
+        match app.popup {
+            Some(Popup::Form(_, ref form)) => assert_eq!(form.message.as_ref().unwrap(), "'src' does not start with an absolute path"),
+            _ => panic!("form closed"),
+        }
+        app.handle_input(&fake, &press(Received::Key(Key::Backspace)));
+        app.handle_input(&fake, &press(Received::Key(Key::Backspace)));
+        app.handle_input(&fake, &press(Received::Key(Key::Backspace)));
+        for c in "/src".chars() {
+            app.handle_input(&fake, &press(Received::Char(c)));
+        }
+        app.handle_input(&fake, &press(Received::Key(Key::Up)));
+        app.handle_input(&fake, &press(Received::Key(Key::Right)));
+        app.handle_input(&fake, &press(Received::Key(Key::Enter)));
+        assert!(app.popup.is_none());
+        assert_eq!(fs::read_to_string(etc.join("leap.nspawn")).unwrap(),
+                   "# from the package\n[Exec]\nBoot=true\nHostname=leap\nPrivateUsers=no\n\n[Network]\nPort=2222:22\n\n[Files]\nBind=/srv:/srv:ro\nBind=/src\n");
+        assert!(app.status.as_ref().unwrap().starts_with("leap: settings written to "));
+        // the one in /etc is used from now on
+        app.handle_input(&fake, &press(Received::Char('e')));
+        app.handle_input(&fake, &press(Received::Key(Key::Enter)));
+        assert_eq!(app.status.as_ref().unwrap(), "leap: settings unchanged");
+
+        // one next to the image is not trusted, only what was changed is written
+        let pool = dir.join("machines");
+        fs::create_dir_all(&pool).unwrap();
+        fs::write(pool.join("tumbleweed.nspawn"), "[Exec]\nBoot=no\n\n[Files]\nBind=/:/host\n").unwrap();
+        fake.add_image("tumbleweed", false, 1<<30);
+        *fake.pool_dir.borrow_mut() = pool.to_string_lossy().into_owned();
+        app.update(&fake).unwrap();
+        app.handle_input(&fake, &press(Received::Key(Key::Down)));
+        app.handle_input(&fake, &press(Received::Char('e')));
+        match app.popup {
+            Some(Popup::Form(FormKind::Settings(ref name), ref form)) => {
+                assert_eq!(name, "tumbleweed");
+                assert_eq!(form.get_text(2), "/:/host");
+                assert!(form.message.as_ref().unwrap().contains("only changes are saved"));
+            },
+            _ => panic!("no settings form"),
+        }
+        app.handle_input(&fake, &press(Received::Key(Key::Left)));
+        app.handle_input(&fake, &press(Received::Key(Key::Enter)));
+        assert_eq!(fs::read_to_string(etc.join("tumbleweed.nspawn")).unwrap(), "[Exec]\nBoot=yes\n");
+    }
+
+    #[test]
+    fn reboot_only_running_machines() {
+        let fake = FakeBackend::with_images(&["leap", "tumbleweed"]);
+        fake.run("tumbleweed");
+        let mut app = app_with(&fake);
+        app.handle_input(&fake, &press(Received::Char('r')));
+        assert!(fake.calls().is_empty());
+        app.handle_input(&fake, &press(Received::Key(Key::Down)));
+        app.handle_input(&fake, &press(Received::Char('r')));
+        assert_eq!(fake.calls(), vec!["kill tumbleweed leader 2"]);
+    }
+
+    #[test]
+    fn shell_only_into_running_machines() {
+        let fake = FakeBackend::with_images(&["leap"]);
+        let mut app = app_with(&fake);
+        assert_eq!(app.handle_input(&fake, &press(Received::Key(Key::Right))), Outcome::Nothing);
+        fake.run("leap");
+        app.update(&fake).unwrap();
+        app.config.shell_users.insert("leap".to_string(), "ludwig".to_string());
+        assert_eq!(app.handle_input(&fake, &press(Received::Key(Key::Right))), Outcome::Redraw);
+        assert_eq!(app.handle_input(&fake, &press(Received::Key(Key::Enter))), Outcome::Shell("leap".to_string(), "ludwig".to_string()));
+        assert!(!app.config_changed);
+    }
+
+    #[test]
+    fn shell_user_is_remembered() {
+        let fake = FakeBackend::with_images(&["leap"]);
+        fake.run("leap");
+        let mut app = app_with(&fake);
+        app.handle_input(&fake, &press(Received::Key(Key::Right)));
+        for _ in 0..config::invoking_user().len() {
+            app.handle_input(&fake, &press(Received::Key(Key::Backspace)));
+        }
+        assert_eq!(app.handle_input(&fake, &press(Received::Key(Key::Enter))), Outcome::Redraw);
+        typed(&mut app, &fake, "nobody");
+        assert_eq!(app.handle_input(&fake, &press(Received::Key(Key::Enter))), Outcome::Shell("leap".to_string(), "nobody".to_string()));
+        assert!(app.config_changed);
+        assert_eq!(app.config.shell_users.get("leap").unwrap(), "nobody");
+    }
+
+    #[test]
+    fn empty_list_ignores_actions() {
+        let fake = FakeBackend::new();
+        let mut app = app_with(&fake);
+        assert_eq!(app.handle_input(&fake, &press(Received::Key(Key::Enter))), Outcome::Nothing);
+        assert_eq!(app.handle_input(&fake, &press(Received::Char('r'))), Outcome::Nothing);
+        assert!(fake.calls().is_empty());
+    }
+
+    #[test]
+    fn start_failure_is_reported_and_dismissed() {
+        let fake = FakeBackend::with_images(&["leap"]);
+        let mut app = app_with(&fake);
+        fake.remove_image("leap").unwrap();
+        assert_eq!(app.handle_input(&fake, &press(Received::Key(Key::Enter))), Outcome::Redraw);
+        assert!(app.pending.is_empty());
+        let (what, e) = app.error.clone().unwrap();
+        assert_eq!(what, "Starting leap failed");
+        assert_eq!(e, Error::NoSuchImage("No image 'leap' known".to_string()));
+        // the key dismissing the dialog does nothing else
+        assert_eq!(app.handle_input(&fake, &press(Received::Char('q'))), Outcome::Redraw);
+        assert!(app.error.is_none());
+    }
+
+    #[test]
+    fn refresh_keeps_list_on_error() {
+        let fake = FakeBackend::with_images(&["leap"]);
+        let mut app = app_with(&fake);
+        *fake.fail.borrow_mut() = Some(Error::AccessDenied("nope".to_string()));
+        app.refresh(&fake);
+        assert_eq!(names(&app), vec!["leap"]);
+        assert_eq!(app.error.as_ref().unwrap().1, Error::AccessDenied("nope".to_string()));
+    }
+
+    #[test]
+    fn unbound_key_shows_hint() {
+        let fake = FakeBackend::with_images(&["leap"]);
+        let mut app = app_with(&fake);
+        assert_eq!(app.handle_input(&fake, &press(Received::Char('x'))), Outcome::Nothing);
+        assert!(app.status.is_some());
+        assert!(fake.calls().is_empty());
+        assert_eq!(app.handle_input(&fake, &press(Received::Key(Key::Down))), Outcome::Redraw);
+        assert!(app.status.is_none());
+    }
+
+    #[test]
+    fn select_by_row() {
+        let fake = FakeBackend::with_images(&["a", "b"]);
+        let mut app = app_with(&fake);
+        assert_eq!(app.select(1), Outcome::Redraw);
+        assert_eq!(app.current, 1);
+        assert_eq!(app.select(2), Outcome::Nothing);
+        assert_eq!(app.current, 1);
+    }
+
+    #[test]
+    fn details_are_cached() {
+        let fake = FakeBackend::with_images(&["leap"]);
+        let mut app = app_with(&fake);
+        app.load_details(&fake);
+        app.load_details(&fake);
+        assert_eq!(fake.calls(), vec!["details leap"]);
+        let details = app.selected_details().unwrap().as_ref().unwrap();
+        assert_eq!(details.os_release["PRETTY_NAME"], "leap Linux");
+        app.handle_input(&fake, &press(Received::Key(Key::F05)));
+        assert!(app.selected_details().is_none());
+        app.load_details(&fake);
+        assert_eq!(fake.calls().len(), 2);
+    }
+
+    #[test]
+    fn machine_info_popup() {
+        let fake = FakeBackend::with_images(&["leap"]);
+        let mut app = app_with(&fake);
+        assert_eq!(app.handle_input(&fake, &press(Received::Char('i'))), Outcome::Nothing);
+        assert!(app.popup.is_none());
+        fake.run("leap");
+        app.update(&fake).unwrap();
+        assert_eq!(app.handle_input(&fake, &press(Received::Char('i'))), Outcome::Redraw);
+        match app.popup {
+            Some(Popup::MachineInfo(ref name, Ok(ref d))) => {
+                assert_eq!(name, "leap");
+                assert_eq!(d.leader, 4711);
+            },
+            ref p => panic!("unexpected popup {:?}", p),
+        }
+        // the next key only closes it
+        assert_eq!(app.handle_input(&fake, &press(Received::Char('q'))), Outcome::Redraw);
+        assert!(app.popup.is_none());
+    }
+
+    #[test]
+    fn help_popup() {
+        let fake = FakeBackend::with_images(&["leap"]);
+        let mut app = app_with(&fake);
+        assert_eq!(app.handle_input(&fake, &press(Received::Char('?'))), Outcome::Redraw);
+        match app.popup {
+            Some(Popup::Message(ref title, ref text)) => {
+                assert_eq!(title, "Keys");
+                assert!(text.contains("    P  Processes"), "{}", text);
+            },
+            ref p => panic!("unexpected popup {:?}", p),
+        }
+        assert_eq!(app.handle_input(&fake, &press(Received::Key(Key::Esc))), Outcome::Redraw);
+        assert!(app.popup.is_none());
+    }
+
+    fn typed(app: &mut App, fake: &FakeBackend, s: &str) {
+        for c in s.chars() {
+            app.handle_input(fake, &press(Received::Char(c)));
+        }
+    }
+
+    #[test]
+    fn clone_selects_new_image() {
+        let fake = FakeBackend::with_images(&["leap", "tumbleweed"]);
+        let mut app = app_with(&fake);
+        assert_eq!(app.handle_input(&fake, &press(Received::Char('c'))), Outcome::Redraw);
+        match app.popup {
+            Some(Popup::Form(FormKind::Clone(ref name), ref form)) => {
+                assert_eq!(name, "leap");
+                assert_eq!(form.get_text(0), "leap-clone");
+            },
+            ref p => panic!("unexpected popup {:?}", p),
+        }
+        // goes into the name instead of quitting
+        typed(&mut app, &fake, "q");
+        app.handle_input(&fake, &press(Received::Key(Key::Tab)));
+        app.handle_input(&fake, &press(Received::Char(' ')));
+        assert_eq!(app.handle_input(&fake, &press(Received::Key(Key::Enter))), Outcome::Redraw);
+        assert!(app.popup.is_none());
+        assert_eq!(app.tasks.len(), 1);
+        assert_eq!(fake.calls(), vec!["clone leap leap-cloneq true"]);
+        for ev in fake.take_events() {
+            assert_eq!(app.handle_bus(ev), Outcome::Update);
+        }
+        app.update(&fake).unwrap();
+        assert!(app.tasks.is_empty());
+        assert_eq!(app.selected().unwrap().name, "leap-cloneq");
+    }
+
+    #[test]
+    fn clone_name_is_validated() {
+        let fake = FakeBackend::with_images(&["leap", "tw"]);
+        let mut app = app_with(&fake);
+        app.handle_input(&fake, &press(Received::Char('c')));
+        for _ in 0.."leap-clone".len() {
+            app.handle_input(&fake, &press(Received::Key(Key::Backspace)));
+        }
+        for name in &["tw", "a b"] {
+            typed(&mut app, &fake, name);
+            assert_eq!(app.handle_input(&fake, &press(Received::Key(Key::Enter))), Outcome::Redraw);
+            match app.popup {
+                Some(Popup::Form(_, ref form)) => assert!(form.message.is_some()),
+                ref p => panic!("unexpected popup {:?}", p),
+            }
+            for _ in 0..name.len() {
+                app.handle_input(&fake, &press(Received::Key(Key::Backspace)));
+            }
+        }
+        assert!(fake.calls().is_empty());
+        app.handle_input(&fake, &press(Received::Key(Key::Esc)));
+        assert!(app.popup.is_none());
+    }
+
+    #[test]
+    fn rename_selects_renamed_image() {
+        let fake = FakeBackend::with_images(&["leap", "tumbleweed"]);
+        let mut app = app_with(&fake);
+        assert_eq!(app.handle_input(&fake, &press(Received::Key(Key::F02))), Outcome::Redraw);
+        typed(&mut app, &fake, "2");
+        assert_eq!(app.handle_input(&fake, &press(Received::Key(Key::Enter))), Outcome::Update);
+        assert_eq!(fake.calls(), vec!["rename leap leap2"]);
+        app.update(&fake).unwrap();
+        assert_eq!(app.selected().unwrap().name, "leap2");
+    }
+
+    #[test]
+    fn remove_asks_first() {
+        let fake = FakeBackend::with_images(&["leap"]);
+        fake.add_image("tumbleweed", true, 3 << 30);
+        let mut app = app_with(&fake);
+        app.current = 1;
+        assert_eq!(app.handle_input(&fake, &press(Received::Key(Key::Del))), Outcome::Redraw);
+        match app.popup {
+            Some(Popup::Confirm(Confirm::Remove(ref name), ref question)) => {
+                assert_eq!(name, "tumbleweed");
+                assert!(question.contains("3G"), "{}", question);
+                assert!(question.contains("read-only"));
+            },
+            ref p => panic!("unexpected popup {:?}", p),
+        }
+        // anything but yes cancels
+        app.handle_input(&fake, &press(Received::Char('n')));
+        assert!(app.popup.is_none());
+        app.handle_input(&fake, &press(Received::Key(Key::Del)));
+        app.handle_input(&fake, &press(Received::Char('y')));
+        assert_eq!(fake.calls(), vec!["remove tumbleweed"]);
+        for ev in fake.take_events() {
+            assert_eq!(app.handle_bus(ev), Outcome::Update);
+        }
+        app.update(&fake).unwrap();
+        assert_eq!(names(&app), vec!["leap"]);
+    }
+
+    #[test]
+    fn running_images_are_not_touched() {
+        let fake = FakeBackend::with_images(&["leap"]);
+        fake.run("leap");
+        let mut app = app_with(&fake);
+        assert_eq!(app.handle_input(&fake, &press(Received::Key(Key::Del))), Outcome::Nothing);
+        assert_eq!(app.handle_input(&fake, &press(Received::Key(Key::F02))), Outcome::Nothing);
+        assert!(app.popup.is_none());
+        assert_eq!(app.status.as_ref().unwrap(), "leap is running, stop it first");
+    }
+
+    #[test]
+    fn toggle_read_only() {
+        let fake = FakeBackend::with_images(&["leap"]);
+        let mut app = app_with(&fake);
+        assert_eq!(app.handle_input(&fake, &press(Received::Char('w'))), Outcome::Update);
+        app.update(&fake).unwrap();
+        assert!(app.images[0].ro);
+        app.handle_input(&fake, &press(Received::Char('w')));
+        assert_eq!(fake.calls(), vec!["ro leap true", "ro leap false"]);
+        assert_eq!(app.status.as_ref().unwrap(), "leap: writable");
+    }
+
+    #[test]
+    fn set_limit() {
+        let fake = FakeBackend::with_images(&["leap"]);
+        let mut app = app_with(&fake);
+        app.handle_input(&fake, &press(Received::Char('l')));
+        typed(&mut app, &fake, "20x");
+        assert_eq!(app.handle_input(&fake, &press(Received::Key(Key::Enter))), Outcome::Redraw);
+        assert!(fake.calls().is_empty());
+        app.handle_input(&fake, &press(Received::Key(Key::Backspace)));
+        typed(&mut app, &fake, "G");
+        assert_eq!(app.handle_input(&fake, &press(Received::Key(Key::Enter))), Outcome::Update);
+        assert!(app.popup.is_none());
+        // an empty input removes the limit
+        app.handle_input(&fake, &press(Received::Char('l')));
+        app.handle_input(&fake, &press(Received::Key(Key::Enter)));
+        assert_eq!(fake.calls(), vec![format!("limit leap {}", 20u64 << 30), format!("limit leap {}", u64::MAX)]);
+    }
+
+    #[test]
+    fn pool_limit() {
+        let fake = FakeBackend::with_images(&["leap"]);
+        let mut app = app_with(&fake);
+        assert_eq!(app.pool.as_ref().unwrap().limit, None);
+        app.handle_input(&fake, &press(Received::Char('L')));
+        typed(&mut app, &fake, "100G");
+        assert_eq!(app.handle_input(&fake, &press(Received::Key(Key::Enter))), Outcome::Update);
+        app.update(&fake).unwrap();
+        assert_eq!(app.pool.as_ref().unwrap().limit, Some(100 << 30));
+    }
+
+    #[test]
+    fn clean_pool_previews_and_reports() {
+        let fake = FakeBackend::with_images(&["leap", "tumbleweed"]);
+        fake.add_image(".old", false, 2 << 30);
+        fake.run("tumbleweed");
+        let mut app = app_with(&fake);
+        app.handle_input(&fake, &press(Received::Char('X')));
+        // switch to all
+        app.handle_input(&fake, &press(Received::Char(' ')));
+        assert_eq!(app.handle_input(&fake, &press(Received::Key(Key::Enter))), Outcome::Redraw);
+        match app.popup {
+            Some(Popup::Confirm(Confirm::CleanPool(ref mode), ref question)) => {
+                assert_eq!(mode, "all");
+                assert!(question.contains(".old") && question.contains("leap"));
+                // running machines are kept
+                assert!(!question.contains("tumbleweed"));
+            },
+            ref p => panic!("unexpected popup {:?}", p),
+        }
+        app.handle_input(&fake, &press(Received::Key(Key::Enter)));
+        assert_eq!(fake.calls(), vec!["clean all"]);
+        for ev in fake.take_events() {
+            app.handle_bus(ev);
+        }
+        match app.popup {
+            Some(Popup::Message(_, ref text)) => assert!(text.starts_with("2 images removed, 3G reclaimed"), "{}", text),
+            ref p => panic!("unexpected popup {:?}", p),
+        }
+    }
+
+    #[test]
+    fn console_tabs() {
+        let fake = FakeBackend::with_images(&["leap"]);
+        fake.run("leap");
+        let mut app = app_with(&fake);
+        app.handle_input(&fake, &press(Received::Char('t')));
+        assert_eq!(app.handle_input(&fake, &press(Received::Key(Key::Enter))), Outcome::Redraw);
+        assert_eq!(fake.calls(), vec!["console leap login"]);
+        assert_eq!(app.active, Some(0));
+        app.set_console_size((100, 30));
+        assert_eq!((app.consoles[0].screen.width, app.consoles[0].screen.height), (100, 30));
+        // keys go to the machine now
+        assert_eq!(app.handle_input(&fake, &press(Received::Char('q'))), Outcome::Nothing);
+        assert_eq!(app.handle_input(&fake, &press(Received::Key(Key::F06))), Outcome::Redraw);
+        assert_eq!(app.active, None);
+        assert_eq!(app.handle_input(&fake, &press(Received::Key(Key::F06))), Outcome::Redraw);
+        assert_eq!(app.active, Some(0));
+        // /dev/null hangs up right away
+        assert_eq!(app.console_output(0), Outcome::Redraw);
+        assert!(app.consoles[0].exited);
+        assert_eq!(app.status.as_ref().unwrap(), "leap: login terminal closed");
+        app.handle_input(&fake, &press(Received::Char('x')));
+        assert!(app.consoles.is_empty());
+        assert_eq!(app.active, None);
+    }
+
+    #[test]
+    fn copy_files() {
+        let fake = FakeBackend::with_images(&["leap"]);
+        fake.run("leap");
+        let mut app = app_with(&fake);
+        app.handle_input(&fake, &press(Received::Char('C')));
+        // the fake machine root is the host root
+        if let Some(Popup::Form(_, ref mut form)) = app.popup {
+            form.set_text(1, "/");
+            form.set_text(2, "/pro");
+            form.focus = 2;
+        }
+        assert_eq!(app.handle_input(&fake, &press(Received::Key(Key::Tab))), Outcome::Redraw);
+        match app.popup {
+            Some(Popup::Form(_, ref form)) => assert_eq!(form.get_text(2), "/proc/"),
+            ref p => panic!("unexpected popup {:?}", p),
+        }
+        app.handle_input(&fake, &press(Received::Key(Key::Down)));
+        app.handle_input(&fake, &press(Received::Char(' ')));
+        app.handle_input(&fake, &press(Received::Key(Key::Enter)));
+        assert_eq!(fake.calls(), vec!["root leap", "copy-to leap / /proc/ true"]);
+        for ev in fake.take_events() {
+            app.handle_bus(ev);
+        }
+        assert!(app.tasks.is_empty());
+        assert_eq!(app.status.as_ref().unwrap(), "Copying / to leap:/proc/: done");
+    }
+
+    #[test]
+    fn copy_needs_absolute_machine_path() {
+        let fake = FakeBackend::with_images(&["leap"]);
+        fake.run("leap");
+        let mut app = app_with(&fake);
+        app.handle_input(&fake, &press(Received::Char('C')));
+        if let Some(Popup::Form(_, ref mut form)) = app.popup {
+            form.set_text(2, "etc");
+        }
+        app.handle_input(&fake, &press(Received::Key(Key::Enter)));
+        match app.popup {
+            Some(Popup::Form(_, ref form)) => assert_eq!(form.message.as_ref().unwrap(), "etc is not an absolute path"),
+            ref p => panic!("unexpected popup {:?}", p),
+        }
+        assert!(fake.calls().is_empty());
+    }
+
+    #[test]
+    fn bind_mounts_are_remembered() {
+        let fake = FakeBackend::with_images(&["leap"]);
+        fake.run("leap");
+        let mut app = app_with(&fake);
+        assert_eq!(app.handle_input(&fake, &press(Received::Char('B'))), Outcome::Nothing);
+        app.handle_input(&fake, &press(Received::Char('b')));
+        if let Some(Popup::Form(_, ref mut form)) = app.popup {
+            assert_eq!(form.get_text(1), "");
+            form.set_text(0, "/tmp");
+            form.set_text(1, "/mnt/tmp");
+        }
+        app.handle_input(&fake, &press(Received::Key(Key::Enter)));
+        assert!(app.popup.is_none());
+        assert!(app.config_changed);
+        assert_eq!(fake.calls(), vec!["bind leap /tmp:/mnt/tmp:mkdir"]);
+        // the same path inside unless told otherwise
+        app.handle_input(&fake, &press(Received::Char('b')));
+        if let Some(Popup::Form(_, ref mut form)) = app.popup {
+            form.set_text(0, "/var/tmp");
+        }
+        app.handle_input(&fake, &press(Received::Key(Key::Enter)));
+        assert_eq!(fake.calls()[1], "bind leap /var/tmp:/var/tmp:mkdir");
+        // after a restart
+        assert_eq!(app.handle_input(&fake, &press(Received::Char('B'))), Outcome::Redraw);
+        assert_eq!(fake.calls().len(), 4);
+        match app.popup {
+            Some(Popup::Message(_, ref text)) => assert_eq!(text, "/tmp → /mnt/tmp: ok\n/var/tmp → /var/tmp: ok\n"),
+            ref p => panic!("unexpected popup {:?}", p),
+        }
+    }
+
+    #[test]
+    fn toggle_autostart() {
+        let fake = FakeBackend::with_images(&["leap"]);
+        let mut app = app_with(&fake);
+        assert!(!app.images[0].autostart);
+        assert_eq!(app.handle_input(&fake, &press(Received::Char('a'))), Outcome::Update);
+        app.update(&fake).unwrap();
+        assert!(app.images[0].autostart);
+        app.handle_input(&fake, &press(Received::Char('a')));
+        app.update(&fake).unwrap();
+        assert!(!app.images[0].autostart);
+        assert_eq!(fake.calls(), vec!["enable leap", "disable leap"]);
+        // changes by others are asked for once systemd reports them
+        fake.enabled.borrow_mut().push("leap".to_string());
+        app.update(&fake).unwrap();
+        assert!(!app.images[0].autostart);
+        assert_eq!(app.handle_bus(BusEvent::UnitFilesChanged), Outcome::Update);
+        app.update(&fake).unwrap();
+        assert!(app.images[0].autostart);
+    }
+
+    #[test]
+    fn freeze_and_thaw() {
+        let fake = FakeBackend::with_images(&["leap"]);
+        fake.run("leap");
+        let mut app = app_with(&fake);
+        assert_eq!(app.handle_input(&fake, &press(Received::Char('p'))), Outcome::Redraw);
+        assert!(app.images[0].frozen);
+        app.update(&fake).unwrap();
+        assert!(app.images[0].frozen);
+        assert_eq!(app.tick(&fake, Instant::now()), Outcome::Redraw);
+        assert!(app.images[0].frozen);
+        app.handle_input(&fake, &press(Received::Char('p')));
+        assert!(!app.images[0].frozen);
+        assert_eq!(fake.calls(), vec!["freeze leap", "thaw leap"]);
+        // paused by someone else
+        fake.frozen.borrow_mut().push("leap".to_string());
+        app.tick(&fake, Instant::now());
+        assert!(app.images[0].frozen);
+    }
+
+    #[test]
+    fn freeze_needs_running_machine() {
+        let fake = FakeBackend::with_images(&["leap"]);
+        let mut app = app_with(&fake);
+        app.handle_input(&fake, &press(Received::Char('p')));
+        assert!(fake.calls().is_empty());
+        assert_eq!(app.status.as_ref().unwrap(), "machine is not running");
+    }
+
+    #[test]
+    fn q_quits() {
+        let fake = FakeBackend::new();
+        let mut app = app_with(&fake);
+        assert_eq!(app.handle_input(&fake, &press(Received::Char('q'))), Outcome::Quit);
+    }
+}
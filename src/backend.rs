@@ -0,0 +1,564 @@
+use dbus;
+use libc;
+use std::collections::HashMap;
+use std::fs::File;
+use std::net::{IpAddr,Ipv4Addr,Ipv6Addr};
+use std::path::Path;
+
+use error::Error;
+
+pub mod bus;
+#[cfg(test)]
+pub mod fake;
+
+pub use self::bus::DbusBackend;
+
+pub const SIGINT: i32 = 2;
+pub const SIGRTMIN_4: i32 = 38;
+
+// what can be sent from the kill dialog. The SIGRTMIN ones are the
+// halt, poweroff and reboot requests systemd understands as pid 1
+pub const SIGNALS: &[(&str, i32)] = &[
+    ("SIGTERM", libc::SIGTERM),
+    ("SIGINT", libc::SIGINT),
+    ("SIGHUP", libc::SIGHUP),
+    ("SIGQUIT", libc::SIGQUIT),
+    ("SIGKILL", libc::SIGKILL),
+    ("SIGUSR1", libc::SIGUSR1),
+    ("SIGUSR2", libc::SIGUSR2),
+    ("SIGSTOP", libc::SIGSTOP),
+    ("SIGCONT", libc::SIGCONT),
+    ("SIGRTMIN+3", 37),
+    ("SIGRTMIN+4", SIGRTMIN_4),
+    ("SIGRTMIN+5", 39),
+];
+
+pub fn signal_number(name: &str) -> Option<i32> {
+    SIGNALS.iter().find(|s| s.0 == name).map(|s| s.1)
+}
+
+#[allow(dead_code)]
+#[derive(Clone)]
+pub struct Machine {
+    pub name: String,
+    pub class: String,
+    pub id: String,
+    pub path: dbus::Path<'static>,
+}
+
+#[allow(dead_code)]
+#[derive(Clone)]
+pub struct Image {
+    pub name: String,
+    pub t: String,
+    pub ro: bool,
+    pub t_created: u64,
+    pub t_modified: u64,
+    pub size: u64,
+    pub path: dbus::Path<'static>,
+    pub machine: Option<Machine>,
+    // enabled under machines.target
+    pub autostart: bool,
+    // the unit of the running machine is frozen
+    pub frozen: bool,
+    // accounting of the unit while running
+    pub usage: Option<Usage>,
+    // since the previous sample
+    pub cpu_percent: Option<f64>,
+    // an ephemeral run of this image, the rest is copied from there
+    pub source: Option<String>,
+}
+
+// host directory to show in a running machine
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct BindMount {
+    pub source: String,
+    pub destination: String,
+    pub read_only: bool,
+    // create the destination if missing
+    pub mkdir: bool,
+}
+
+impl BindMount {
+
+    // source:destination[:ro,mkdir], like Bind= in .nspawn files without escaping
+    pub fn parse(spec: &str) -> Option<BindMount> {
+        let mut parts = spec.splitn(3, ':');
+        let source = parts.next().filter(|s| !s.is_empty())?.to_string();
+        let destination = parts.next().filter(|s| !s.is_empty()).unwrap_or(&source).to_string();
+        let options: Vec<&str> = parts.next().map_or(Vec::new(), |o| o.split(',').collect());
+        Some(BindMount { source, destination, read_only: options.contains(&"ro"), mkdir: options.contains(&"mkdir") })
+    }
+
+    pub fn spec(&self) -> String {
+        let mut options = Vec::new();
+        if self.read_only {
+            options.push("ro");
+        }
+        if self.mkdir {
+            options.push("mkdir");
+        }
+        let mut spec = format!("{}:{}", self.source, self.destination);
+        if !options.is_empty() {
+            spec += &format!(":{}", options.join(","));
+        }
+        spec
+    }
+}
+
+// a run of an image that systemd-nspawn@.service can't do
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct StartOptions {
+    // on a snapshot that is thrown away when the machine stops
+    pub ephemeral: bool,
+    pub read_only: bool,
+    pub binds: Vec<BindMount>,
+    pub zone: Option<String>,
+    // instead of the default target of the image
+    pub target: Option<String>,
+    // path of the image if the machine is named differently. nspawn looks
+    // for <machine>.nspawn then, the settings of the image don't apply
+    pub directory: Option<String>,
+    // the image of an ephemeral run named differently, kept in the
+    // environment of the unit so the run is found again, see machine_source
+    pub source: Option<String>,
+}
+
+impl StartOptions {
+
+    // what goes after ExecStart= of systemd-nspawn@.service
+    pub fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(ref directory) = self.directory {
+            args.push(format!("--directory={}", directory));
+        }
+        if self.ephemeral {
+            args.push("--ephemeral".to_string());
+        }
+        if self.read_only {
+            args.push("--read-only".to_string());
+        }
+        // nspawn has no mkdir option, it creates missing destinations anyway
+        for b in &self.binds {
+            args.push(format!("--bind{}={}:{}", if b.read_only { "-ro" } else { "" }, b.source, b.destination));
+        }
+        // on top of the veth link of the unit
+        if let Some(ref zone) = self.zone {
+            args.push(format!("--network-zone={}", zone));
+        }
+        // after --boot the rest goes to the init of the machine
+        if let Some(ref target) = self.target {
+            args.push(format!("systemd.unit={}", target));
+        }
+        args
+    }
+}
+
+// what importd takes, it figures out the compression itself
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum ImportFormat {
+    Tar,
+    Raw,
+    Directory,
+}
+
+impl ImportFormat {
+
+    pub fn parse(name: &str) -> Option<ImportFormat> {
+        match name {
+            "tar" => Some(ImportFormat::Tar),
+            "raw" => Some(ImportFormat::Raw),
+            "directory" => Some(ImportFormat::Directory),
+            _ => None,
+        }
+    }
+
+    // by the file name, anything that's no tarball is taken as a disk image
+    pub fn guess(path: &Path) -> ImportFormat {
+        let name = path.file_name().map_or(String::new(), |n| n.to_string_lossy().into_owned());
+        if path.is_dir() {
+            ImportFormat::Directory
+        } else if name.contains(".tar") || name.ends_with(".tgz") || name.ends_with(".txz") {
+            ImportFormat::Tar
+        } else {
+            ImportFormat::Raw
+        }
+    }
+
+    // the image name importd would pick, the file name without the suffixes
+    pub fn image_name(path: &Path) -> String {
+        let name = path.file_name().map_or(String::new(), |n| n.to_string_lossy().into_owned());
+        let mut name = name.as_str();
+        for suffix in &[".gz", ".xz", ".bz2", ".zst", ".tar", ".tgz", ".txz", ".raw", ".qcow2"] {
+            name = name.strip_suffix(suffix).unwrap_or(name);
+        }
+        name.to_string()
+    }
+}
+
+// an import or download done by importd
+#[derive(Clone,Debug,PartialEq)]
+pub struct Transfer {
+    pub id: u32,
+    pub path: dbus::Path<'static>,
+    // like import-tar or pull-raw
+    pub kind: String,
+    // name of the image
+    pub local: String,
+    // 0 to 1, None until importd knows
+    pub progress: Option<f64>,
+}
+
+impl Transfer {
+
+    pub fn describe(&self) -> String {
+        let what = match self.kind.split('-').next().unwrap_or("") {
+            "import" => "Importing",
+            "export" => "Exporting",
+            "pull" => "Downloading",
+            _ => "Transferring",
+        };
+        match self.progress {
+            Some(p) => format!("{} {} {:.0}%", what, self.local, p * 100.0),
+            None => format!("{} {}", what, self.local),
+        }
+    }
+}
+
+// where machined keeps images and how full it is
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct Pool {
+    pub path: String,
+    // None if unknown, e.g. not on btrfs
+    pub usage: Option<u64>,
+    pub limit: Option<u64>,
+}
+
+// resource accounting of a unit, None where it's not enabled
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub struct Usage {
+    pub memory: Option<u64>,
+    // ns of CPU time used so far
+    pub cpu: Option<u64>,
+    pub tasks: Option<u64>,
+    // bytes read and written so far
+    pub io: Option<u64>,
+    // paused by the cgroup freezer
+    pub frozen: bool,
+}
+
+// a process in the cgroup of a unit, as GetUnitProcesses reports it
+#[derive(Clone,Debug,PartialEq)]
+pub struct Process {
+    pub cgroup: String,
+    pub pid: u32,
+    // empty for kernel threads
+    pub command: String,
+}
+
+// what machined can tell about the content of an image
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct ImageDetails {
+    pub os_release: HashMap<String, String>,
+    pub hostname: Option<String>,
+    pub machine_id: Option<String>,
+    pub machine_info: HashMap<String, String>,
+    // quota of the image, if the storage supports it
+    pub limit: Option<u64>,
+}
+
+// runtime information about a running machine
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct MachineDetails {
+    pub class: String,
+    pub id: String,
+    pub leader: u32,
+    pub service: String,
+    pub unit: String,
+    pub addresses: Vec<IpAddr>,
+    pub os_release: HashMap<String, String>,
+}
+
+// signals forwarded from the bus into the event loop
+#[allow(dead_code)]
+#[derive(Debug)]
+pub enum BusEvent {
+    MachineNew(String),
+    MachineRemoved(String),
+    JobRemoved { job: dbus::Path<'static>, unit: String, result: String },
+    // something was enabled or disabled
+    UnitFilesChanged,
+    // systemd forgot a unit, transient ones are gone then
+    UnitRemoved(String),
+    TransferNew(u32),
+    TransferRemoved { id: u32, result: String },
+    TaskDone(Task, Result<TaskOutput, Error>),
+}
+
+// operations that may take minutes, run in the background
+#[derive(Clone,Debug,PartialEq)]
+pub enum Task {
+    Clone { name: String, new_name: String, read_only: bool },
+    Remove { name: String },
+    // snapshot names and sizes, those still in use are skipped
+    RemoveSnapshots { snapshots: Vec<(String, u64)> },
+    // mode is "hidden" or "all"
+    CleanPool { mode: String },
+    Copy { name: String, to_machine: bool, host: String, path: String, replace: bool },
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub enum TaskOutput {
+    Nothing,
+    // image names and the space freed
+    Removed(Vec<(String, u64)>),
+}
+
+impl Task {
+
+    pub fn describe(&self) -> String {
+        match *self {
+            Task::Clone { ref name, ref new_name, .. } => format!("Cloning {} to {}", name, new_name),
+            Task::Remove { ref name } => format!("Removing {}", name),
+            Task::RemoveSnapshots { .. } => "Removing stale snapshots".to_string(),
+            Task::CleanPool { ref mode } => format!("Cleaning pool ({} images)", mode),
+            Task::Copy { ref name, to_machine: true, ref host, ref path, .. } => format!("Copying {} to {}:{}", host, name, path),
+            Task::Copy { ref name, to_machine: false, ref host, ref path, .. } => format!("Copying {}:{} to {}", name, path, host),
+        }
+    }
+
+    pub fn run(&self, backend: &dyn MachineBackend) -> Result<TaskOutput, Error> {
+        match *self {
+            Task::Clone { ref name, ref new_name, read_only } => backend.clone_image(name, new_name, read_only)?,
+            Task::Remove { ref name } => backend.remove_image(name)?,
+            // machined refuses to remove the ones a machine holds the lock of
+            Task::RemoveSnapshots { ref snapshots } => {
+                return Ok(TaskOutput::Removed(snapshots.iter().filter(|s| backend.remove_image(&s.0).is_ok()).cloned().collect()));
+            },
+            Task::CleanPool { ref mode } => return Ok(TaskOutput::Removed(backend.clean_pool(mode)?)),
+            Task::Copy { ref name, to_machine: true, ref host, ref path, replace } => backend.copy_to_machine(name, host, path, replace)?,
+            Task::Copy { ref name, to_machine: false, ref host, ref path, replace } => backend.copy_from_machine(name, path, host, replace)?,
+        }
+        Ok(TaskOutput::Nothing)
+    }
+}
+
+// everything mat needs from machined and systemd
+pub trait MachineBackend {
+    fn list_images(&self) -> Result<Vec<Image>, Error>;
+    fn list_machines(&self) -> Result<Vec<Machine>, Error>;
+
+    // returns the systemd job path
+    fn start_machine(&self, name: &str) -> Result<dbus::Path<'static>, Error>;
+    // as a transient unit, see transient_unit_name
+    fn start_machine_with(&self, name: &str, options: &StartOptions) -> Result<dbus::Path<'static>, Error>;
+    // of a running machine, the instance of systemd-nspawn@.service if it's not running
+    fn machine_unit(&self, name: &str) -> String;
+    // StartOptions::source of a machine started with start_machine_with
+    fn machine_source(&self, name: &str) -> Option<String>;
+    fn kill_machine(&self, name: &str, who: &str, signal: i32) -> Result<(), Error>;
+    // kills all processes of the machine right away
+    fn terminate_machine(&self, name: &str) -> Result<(), Error>;
+    // login shell of user in the machine, returns the pty master
+    fn open_shell(&self, name: &str, user: &str) -> Result<File, Error>;
+    // getty on a new pty if login is set, otherwise just the pty
+    fn open_console(&self, name: &str, login: bool) -> Result<File, Error>;
+    fn open_root_directory(&self, name: &str) -> Result<File, Error>;
+    fn bind_mount(&self, name: &str, bind: &BindMount) -> Result<(), Error>;
+
+    // the images whose nspawn unit is enabled, in one call
+    fn autostart(&self) -> Result<Vec<String>, Error>;
+    // enables or disables the nspawn unit of the image
+    fn set_autostart(&self, name: &str, enable: bool) -> Result<(), Error>;
+
+    // pauses all processes of the machine via the cgroup freezer
+    fn freeze_machine(&self, name: &str) -> Result<(), Error>;
+    fn thaw_machine(&self, name: &str) -> Result<(), Error>;
+    // replace overwrites existing files at the destination
+    fn copy_to_machine(&self, name: &str, source: &str, destination: &str, replace: bool) -> Result<(), Error>;
+    fn copy_from_machine(&self, name: &str, source: &str, destination: &str, replace: bool) -> Result<(), Error>;
+
+    fn clone_image(&self, name: &str, new_name: &str, read_only: bool) -> Result<(), Error>;
+    fn rename_image(&self, name: &str, new_name: &str) -> Result<(), Error>;
+    fn remove_image(&self, name: &str) -> Result<(), Error>;
+    fn mark_image_read_only(&self, name: &str, read_only: bool) -> Result<(), Error>;
+    // u64::MAX removes the limit
+    fn set_image_limit(&self, name: &str, size: u64) -> Result<(), Error>;
+    // where the image is on the host
+    fn image_path(&self, name: &str) -> Result<String, Error>;
+
+    fn pool(&self) -> Result<Pool, Error>;
+    // u64::MAX removes the limit
+    fn set_pool_limit(&self, size: u64) -> Result<(), Error>;
+    fn clean_pool(&self, mode: &str) -> Result<Vec<(String, u64)>, Error>;
+
+    fn image_details(&self, name: &str) -> Result<ImageDetails, Error>;
+    fn machine_details(&self, machine: &Machine) -> Result<MachineDetails, Error>;
+
+    fn unit_processes(&self, unit: &str) -> Result<Vec<Process>, Error>;
+    // whom is main, control or all
+    fn kill_unit(&self, unit: &str, whom: &str, signal: i32) -> Result<(), Error>;
+    fn kill_process(&self, pid: u32, signal: i32) -> Result<(), Error>;
+    fn unit_usage(&self, unit: &str) -> Result<Usage, Error>;
+
+    // source is a file or directory on the host, importd gets it opened
+    fn import_image(&self, format: ImportFormat, source: &str, name: &str, force: bool, read_only: bool) -> Result<Transfer, Error>;
+    fn list_transfers(&self) -> Result<Vec<Transfer>, Error>;
+    fn transfer_progress(&self, transfer: &Transfer) -> Result<f64, Error>;
+
+    // run the task in the background, a TaskDone event reports the result
+    fn spawn(&self, task: Task);
+
+    fn reboot_machine(&self, name: &str) -> Result<(), Error> {
+        self.kill_machine(name, "leader", SIGINT)
+    }
+
+    fn poweroff_machine(&self, name: &str) -> Result<(), Error> {
+        self.kill_machine(name, "leader", SIGRTMIN_4)
+    }
+}
+
+pub fn unit_name(image: &str) -> String {
+    format!("systemd-nspawn@{}.service", image)
+}
+
+// where mat runs an image with StartOptions
+pub fn transient_unit_name(image: &str) -> String {
+    format!("mat-nspawn-{}.service", image)
+}
+
+// the machine of a unit named by transient_unit_name
+pub fn transient_machine(unit: &str) -> Option<&str> {
+    unit.strip_prefix("mat-nspawn-")?.strip_suffix(".service")
+}
+
+// machine name of an ephemeral run of an image, there may be more than one
+pub fn ephemeral_name(image: &str, n: u32) -> String {
+    format!("{}.eph{}", image, n)
+}
+
+// the image of a snapshot nspawn --ephemeral made next to it, named
+// .#machine.<image> and 16 random hex digits
+pub fn snapshot_source(snapshot: &str) -> Option<&str> {
+    let rest = snapshot.strip_prefix(".#machine.")?;
+    let split = rest.len().checked_sub(16)?;
+    let (image, random) = (rest.get(..split)?, &rest[split..]);
+    if !image.is_empty() && random.bytes().all(|b| b.is_ascii_hexdigit()) {
+        Some(image)
+    } else {
+        None
+    }
+}
+
+// images need to be usable as machine names, so apply the hostname rules
+// machined uses for those
+pub fn check_image_name(name: &str) -> Result<(), String> {
+    if name.is_empty() {
+        return Err("name must not be empty".to_string());
+    }
+    if name.len() > 64 {
+        return Err("name must not be longer than 64 characters".to_string());
+    }
+    if let Some(c) = name.chars().find(|&c| !(c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')) {
+        return Err(format!("'{}' is not allowed, only letters, digits, '-', '_' and '.'", c));
+    }
+    if name.starts_with('.') || name.ends_with('.') || name.contains("..") {
+        return Err("'.' is only allowed between other characters".to_string());
+    }
+    Ok(())
+}
+
+// object path label like sd_bus_path_encode does it
+pub fn bus_label(name: &str) -> String {
+    name.bytes().enumerate().map(|(i, b)| if b.is_ascii_alphabetic() || (i > 0 && b.is_ascii_digit()) { (b as char).to_string() } else { format!("_{:02x}", b) }).collect()
+}
+
+// GetMachineAddresses returns (AF_INET/AF_INET6, raw bytes) pairs
+pub fn decode_address(family: i32, bytes: &[u8]) -> Option<IpAddr> {
+    match (family, bytes.len()) {
+        (libc::AF_INET, 4) => Some(IpAddr::V4(Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3]))),
+        (libc::AF_INET6, 16) => {
+            let mut a = [0u8; 16];
+            a.copy_from_slice(bytes);
+            Some(IpAddr::V6(Ipv6Addr::from(a)))
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_names() {
+        assert!(check_image_name("leap-15.5_x86").is_ok());
+        assert!(check_image_name("").is_err());
+        assert!(check_image_name(".hidden").is_err());
+        assert!(check_image_name("a..b").is_err());
+        assert!(check_image_name("with space").is_err());
+        assert!(check_image_name(&"x".repeat(65)).is_err());
+    }
+
+    #[test]
+    fn bind_specs() {
+        let b = BindMount::parse("/src:/usr/src:ro,mkdir").unwrap();
+        assert_eq!(b, BindMount { source: "/src".to_string(), destination: "/usr/src".to_string(), read_only: true, mkdir: true });
+        assert_eq!(BindMount::parse(&b.spec()), Some(b));
+        assert_eq!(BindMount::parse("/srv").unwrap().destination, "/srv");
+        assert_eq!(BindMount::parse(""), None);
+    }
+
+    #[test]
+    fn start_command() {
+        assert!(StartOptions::default().args().is_empty());
+        let options = StartOptions {
+            ephemeral: true,
+            read_only: true,
+            binds: vec![BindMount::parse("/src:/usr/src:ro").unwrap(), BindMount::parse("/home").unwrap()],
+            zone: Some("dev".to_string()),
+            target: Some("rescue.target".to_string()),
+            directory: None,
+            source: None,
+        };
+        assert_eq!(options.args(), ["--ephemeral", "--read-only", "--bind-ro=/src:/usr/src", "--bind=/home:/home", "--network-zone=dev", "systemd.unit=rescue.target"]);
+    }
+
+    #[test]
+    fn object_path_labels() {
+        assert_eq!(bus_label("systemd-nspawn@leap.service"), "systemd_2dnspawn_40leap_2eservice");
+        assert_eq!(bus_label("0ab"), "_30ab");
+    }
+
+    #[test]
+    fn ephemeral_names() {
+        assert_eq!(ephemeral_name("leap-15.5", 2), "leap-15.5.eph2");
+        assert_eq!(transient_machine(&transient_unit_name("leap-15.5.eph2")), Some("leap-15.5.eph2"));
+        assert_eq!(transient_machine(&unit_name("leap")), None);
+        assert_eq!(snapshot_source(".#machine.leap-15.50123456789abcdef"), Some("leap-15.5"));
+        assert_eq!(snapshot_source(".#machine.0123456789abcdef"), None);
+        assert_eq!(snapshot_source(".#machine.leap0123456789abcdeg"), None);
+        assert_eq!(snapshot_source(".#leap.tar0123456789abcdef"), None);
+        assert_eq!(snapshot_source(".hidden"), None);
+    }
+
+    #[test]
+    fn import_formats() {
+        assert_eq!(ImportFormat::guess(Path::new("/srv/leap.tar.xz")), ImportFormat::Tar);
+        assert_eq!(ImportFormat::guess(Path::new("/srv/leap.tgz")), ImportFormat::Tar);
+        assert_eq!(ImportFormat::guess(Path::new("/srv/leap.raw")), ImportFormat::Raw);
+        assert_eq!(ImportFormat::guess(Path::new("/")), ImportFormat::Directory);
+        assert_eq!(ImportFormat::image_name(Path::new("/srv/leap-15.5.tar.xz")), "leap-15.5");
+        assert_eq!(ImportFormat::image_name(Path::new("/srv/tw.qcow2")), "tw");
+        assert_eq!(ImportFormat::image_name(Path::new("/srv/golden/")), "golden");
+    }
+
+    #[test]
+    fn addresses() {
+        assert_eq!(decode_address(libc::AF_INET, &[10, 0, 0, 2]), Some("10.0.0.2".parse().unwrap()));
+        let v6 = [0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+        assert_eq!(decode_address(libc::AF_INET6, &v6), Some("fe80::1".parse().unwrap()));
+        assert_eq!(decode_address(libc::AF_INET, &v6), None);
+        assert_eq!(decode_address(0, &[]), None);
+    }
+}
@@ -0,0 +1,391 @@
+use dbus;
+use libc;
+use dbus::blocking::{Connection,Proxy};
+use dbus::blocking::stdintf::org_freedesktop_dbus::Properties;
+use dbus::Message;
+use dbus::arg::{OwnedFd,RefArg,Variant};
+use std::cell::RefCell;
+use std::env;
+use std::fs::File;
+use std::io;
+use std::os::unix::io::{FromRawFd,IntoRawFd};
+use std::time::Duration;
+use std::sync::mpsc;
+use std::thread;
+
+use machined::manager::{OrgFreedesktopMachine1Manager,OrgFreedesktopMachine1ManagerMachineNew,OrgFreedesktopMachine1ManagerMachineRemoved};
+use systemd::manager::{OrgFreedesktopSystemd1Manager,OrgFreedesktopSystemd1ManagerJobRemoved,OrgFreedesktopSystemd1ManagerUnitFilesChanged,OrgFreedesktopSystemd1ManagerUnitRemoved};
+use import1::manager::{OrgFreedesktopImport1Manager,OrgFreedesktopImport1ManagerTransferNew,OrgFreedesktopImport1ManagerTransferRemoved};
+use error::Error;
+use format::hex;
+use super::{MachineBackend,BusEvent,Task,BindMount,Machine,MachineDetails,Image,ImageDetails,Pool,Process,Usage,StartOptions,ImportFormat,Transfer,decode_address,bus_label,unit_name,transient_unit_name};
+
+// the [Service] settings of systemd-nspawn@.service a transient unit takes
+// over, with the values of the installed one. Newer ones only if it has them
+const SERVICE_SETTINGS: &[&str] = &["Type", "KillMode", "Slice", "Delegate", "DelegateSubgroup", "TasksMax", "WatchdogUSec",
+    "DevicePolicy", "DeviceAllow", "SuccessExitStatus", "RestartForceExitStatus"];
+
+// set on transient units of ephemeral runs to the image they run
+const SOURCE_VARIABLE: &str = "MAT_SOURCE";
+
+// MACHINE_COPY_REPLACE in machined
+const COPY_REPLACE: u64 = 1;
+
+// machined replies to clone and friends only when done
+const TASK_TIMEOUT: Duration = Duration::from_secs(3600);
+
+pub struct DbusBackend<'a> {
+    conn: &'a Connection,
+    machined: Proxy<'a, &'a Connection>,
+    systemd: Proxy<'a, &'a Connection>,
+    importd: Proxy<'a, &'a Connection>,
+    events: RefCell<Option<mpsc::Sender<BusEvent>>>,
+}
+
+impl<'a> DbusBackend<'a> {
+
+    pub fn new(conn: &'a Connection) -> DbusBackend<'a> {
+        DbusBackend::with_timeout(conn, Duration::from_millis(5000))
+    }
+
+    pub fn with_timeout(conn: &'a Connection, timeout: Duration) -> DbusBackend<'a> {
+        let machined = conn.with_proxy("org.freedesktop.machine1", "/org/freedesktop/machine1", timeout);
+        let systemd = conn.with_proxy("org.freedesktop.systemd1", "/org/freedesktop/systemd1", timeout);
+        let importd = conn.with_proxy("org.freedesktop.import1", "/org/freedesktop/import1", timeout);
+        DbusBackend { conn, machined, systemd, importd, events: RefCell::new(None) }
+    }
+
+    fn image_limit(&self, name: &str) -> Result<u64, dbus::Error> {
+        let path = self.machined.get_image(name)?;
+        self.conn.with_proxy("org.freedesktop.machine1", path, Duration::from_millis(5000)).get("org.freedesktop.machine1.Image", "Limit")
+    }
+
+    pub fn connection(&self) -> &Connection {
+        self.conn
+    }
+
+    // forward the signals we care about into the event loop
+    pub fn subscribe(&self, tx: mpsc::Sender<BusEvent>) -> Result<(), dbus::Error> {
+        *self.events.borrow_mut() = Some(tx.clone());
+        let t = tx.clone();
+        self.machined.match_signal(move |s: OrgFreedesktopMachine1ManagerMachineNew, _: &Connection, _: &Message| {
+            t.send(BusEvent::MachineNew(s.machine)).is_ok()
+        })?;
+        let t = tx.clone();
+        self.machined.match_signal(move |s: OrgFreedesktopMachine1ManagerMachineRemoved, _: &Connection, _: &Message| {
+            t.send(BusEvent::MachineRemoved(s.machine)).is_ok()
+        })?;
+        let t = tx.clone();
+        self.importd.match_signal(move |s: OrgFreedesktopImport1ManagerTransferNew, _: &Connection, _: &Message| {
+            t.send(BusEvent::TransferNew(s.transfer_id)).is_ok()
+        })?;
+        let t = tx.clone();
+        self.importd.match_signal(move |s: OrgFreedesktopImport1ManagerTransferRemoved, _: &Connection, _: &Message| {
+            t.send(BusEvent::TransferRemoved { id: s.transfer_id, result: s.result }).is_ok()
+        })?;
+        let t = tx.clone();
+        self.systemd.match_signal(move |s: OrgFreedesktopSystemd1ManagerJobRemoved, _: &Connection, _: &Message| {
+            t.send(BusEvent::JobRemoved { job: s.job, unit: s.unit, result: s.result }).is_ok()
+        })?;
+        let t = tx.clone();
+        self.systemd.match_signal(move |_: OrgFreedesktopSystemd1ManagerUnitFilesChanged, _: &Connection, _: &Message| {
+            t.send(BusEvent::UnitFilesChanged).is_ok()
+        })?;
+        let t = tx;
+        self.systemd.match_signal(move |s: OrgFreedesktopSystemd1ManagerUnitRemoved, _: &Connection, _: &Message| {
+            t.send(BusEvent::UnitRemoved(s.id)).is_ok()
+        })?;
+        // systemd only emits these signals if someone subscribed
+        self.systemd.subscribe()
+    }
+}
+
+impl<'a> MachineBackend for DbusBackend<'a> {
+
+    fn list_images(&self) -> Result<Vec<Image>, Error> {
+        Ok(self.machined.list_images()?.into_iter().map(|i| {
+            Image { name: i.0, t: i.1, ro: i.2, t_created: i.3, t_modified: i.4, size: i.5, path: i.6, machine: None, autostart: false, frozen: false, usage: None, cpu_percent: None, source: None }
+        }).collect())
+    }
+
+    fn list_machines(&self) -> Result<Vec<Machine>, Error> {
+        Ok(self.machined.list_machines()?.into_iter().map(|i| {
+            Machine { name: i.0, class: i.1, id: i.2, path: i.3 }
+        }).collect())
+    }
+
+    fn start_machine(&self, name: &str) -> Result<dbus::Path<'static>, Error> {
+        Ok(self.systemd.start_unit(&unit_name(name), "fail")?)
+    }
+
+    // a transient unit like the instance of systemd-nspawn@.service for the
+    // machine, read from systemd, with the options added to its command line
+    fn start_machine_with(&self, name: &str, options: &StartOptions) -> Result<dbus::Path<'static>, Error> {
+        let unit = unit_name(name);
+        let mut template = self.conn.with_proxy("org.freedesktop.systemd1", format!("/org/freedesktop/systemd1/unit/{}", bus_label(&unit)), Duration::from_millis(5000)).get_all("")?;
+        // a(sasbttttuii), the path and argv of each command
+        let command = template.get("ExecStart").and_then(|v| v.0.as_iter()?.next()?.as_iter().and_then(|mut c| {
+            let path = c.next()?.as_str()?.to_string();
+            let argv: Vec<String> = c.next()?.as_iter()?.filter_map(|a| a.as_str().map(|a| a.to_string())).collect();
+            Some((path, argv))
+        }));
+        let (path, mut argv) = command.ok_or_else(|| Error::NoSuchUnit(format!("{} has no command to start", unit)))?;
+        argv.extend(options.args());
+        let mut properties: Vec<(&str, Variant<Box<dyn RefArg>>)> = vec![
+            ("Description", Variant(Box::new(format!("Container {}", name)))),
+            ("ExecStart", Variant(Box::new(vec![(path, argv, false)]))),
+            // a failed run must not block the next one
+            ("CollectMode", Variant(Box::new("inactive-or-failed".to_string()))),
+        ];
+        // SuccessExitStatus and RestartForceExitStatus have 133, the exit
+        // status of nspawn when the machine rebooted
+        properties.extend(SERVICE_SETTINGS.iter().filter_map(|&s| Some((s, template.remove(s)?))));
+        // nspawn doesn't pass its environment on to the machine
+        if let Some(ref source) = options.source {
+            properties.push(("Environment", Variant(Box::new(vec![format!("{}={}", SOURCE_VARIABLE, source)]))));
+        }
+        Ok(self.systemd.start_transient_unit(&transient_unit_name(name), "fail", properties, Vec::new())?)
+    }
+
+    fn machine_unit(&self, name: &str) -> String {
+        let unit = || -> Result<String, dbus::Error> {
+            let path = self.machined.get_machine(name)?;
+            self.conn.with_proxy("org.freedesktop.machine1", path, Duration::from_millis(5000)).get("org.freedesktop.machine1.Machine", "Unit")
+        };
+        unit().unwrap_or_else(|_| unit_name(name))
+    }
+
+    // read back from the environment start_machine_with gave the unit
+    fn machine_source(&self, name: &str) -> Option<String> {
+        let path = self.systemd.get_unit(&transient_unit_name(name)).ok()?;
+        let environment: Vec<String> = self.conn.with_proxy("org.freedesktop.systemd1", path, Duration::from_millis(5000)).get("org.freedesktop.systemd1.Service", "Environment").ok()?;
+        let prefix = format!("{}=", SOURCE_VARIABLE);
+        environment.iter().find_map(|e| e.strip_prefix(&prefix)).map(|s| s.to_string())
+    }
+
+    fn kill_machine(&self, name: &str, who: &str, signal: i32) -> Result<(), Error> {
+        Ok(self.machined.kill_machine(name, who, signal)?)
+    }
+
+    fn terminate_machine(&self, name: &str) -> Result<(), Error> {
+        Ok(self.machined.terminate_machine(name)?)
+    }
+
+    fn open_shell(&self, name: &str, user: &str) -> Result<File, Error> {
+        let term = format!("TERM={}", env::var("TERM").unwrap_or_else(|_| "vt220".to_string()));
+        // empty path and arguments give the user's login shell
+        let (fd, _) = self.machined.open_machine_shell(name, user, "", Vec::new(), vec![term.as_str()])?;
+        Ok(unsafe { File::from_raw_fd(fd.into_raw_fd()) })
+    }
+
+    fn open_console(&self, name: &str, login: bool) -> Result<File, Error> {
+        let (fd, _) = if login {
+            self.machined.open_machine_login(name)?
+        } else {
+            self.machined.open_machine_pty(name)?
+        };
+        Ok(unsafe { File::from_raw_fd(fd.into_raw_fd()) })
+    }
+
+    fn open_root_directory(&self, name: &str) -> Result<File, Error> {
+        let fd = self.machined.open_machine_root_directory(name)?;
+        Ok(unsafe { File::from_raw_fd(fd.into_raw_fd()) })
+    }
+
+    fn bind_mount(&self, name: &str, bind: &BindMount) -> Result<(), Error> {
+        Ok(self.machined.bind_mount_machine(name, &bind.source, &bind.destination, bind.read_only, bind.mkdir)?)
+    }
+
+    // enabled instances are links in machines.target.wants, which ListUnitFiles
+    // doesn't look into, the target knows them since the last reload
+    fn autostart(&self) -> Result<Vec<String>, Error> {
+        let target = self.conn.with_proxy("org.freedesktop.systemd1", "/org/freedesktop/systemd1/unit/machines_2etarget", Duration::from_millis(5000));
+        let wants: Vec<String> = target.get("org.freedesktop.systemd1.Unit", "Wants")?;
+        Ok(wants.iter().filter_map(|u| u.strip_prefix("systemd-nspawn@")?.strip_suffix(".service")).map(|n| n.to_string()).collect())
+    }
+
+    // systemd-nspawn@.service has WantedBy=machines.target
+    fn set_autostart(&self, name: &str, enable: bool) -> Result<(), Error> {
+        let unit = unit_name(name);
+        if enable {
+            self.systemd.enable_unit_files(vec![unit.as_str()], false, false)?;
+        } else {
+            self.systemd.disable_unit_files(vec![unit.as_str()], false)?;
+        }
+        Ok(self.systemd.reload()?)
+    }
+
+    fn freeze_machine(&self, name: &str) -> Result<(), Error> {
+        Ok(self.systemd.freeze_unit(&self.machine_unit(name))?)
+    }
+
+    fn thaw_machine(&self, name: &str) -> Result<(), Error> {
+        Ok(self.systemd.thaw_unit(&self.machine_unit(name))?)
+    }
+
+    // the variants with flags are newer, only use them when needed
+    fn copy_to_machine(&self, name: &str, source: &str, destination: &str, replace: bool) -> Result<(), Error> {
+        if replace {
+            Ok(self.machined.copy_to_machine_with_flags(name, source, destination, COPY_REPLACE)?)
+        } else {
+            Ok(self.machined.copy_to_machine(name, source, destination)?)
+        }
+    }
+
+    fn copy_from_machine(&self, name: &str, source: &str, destination: &str, replace: bool) -> Result<(), Error> {
+        if replace {
+            Ok(self.machined.copy_from_machine_with_flags(name, source, destination, COPY_REPLACE)?)
+        } else {
+            Ok(self.machined.copy_from_machine(name, source, destination)?)
+        }
+    }
+
+    fn clone_image(&self, name: &str, new_name: &str, read_only: bool) -> Result<(), Error> {
+        Ok(self.machined.clone_image(name, new_name, read_only)?)
+    }
+
+    fn rename_image(&self, name: &str, new_name: &str) -> Result<(), Error> {
+        Ok(self.machined.rename_image(name, new_name)?)
+    }
+
+    fn remove_image(&self, name: &str) -> Result<(), Error> {
+        Ok(self.machined.remove_image(name)?)
+    }
+
+    fn mark_image_read_only(&self, name: &str, read_only: bool) -> Result<(), Error> {
+        Ok(self.machined.mark_image_read_only(name, read_only)?)
+    }
+
+    fn set_image_limit(&self, name: &str, size: u64) -> Result<(), Error> {
+        Ok(self.machined.set_image_limit(name, size)?)
+    }
+
+    fn image_path(&self, name: &str) -> Result<String, Error> {
+        let path = self.machined.get_image(name)?;
+        Ok(self.conn.with_proxy("org.freedesktop.machine1", path, Duration::from_millis(5000)).get("org.freedesktop.machine1.Image", "Path")?)
+    }
+
+    fn pool(&self) -> Result<Pool, Error> {
+        // machined uses -1 for unknown values
+        let known = |v: u64| if v == u64::MAX { None } else { Some(v) };
+        Ok(Pool {
+            path: self.machined.pool_path()?,
+            usage: known(self.machined.pool_usage()?),
+            limit: known(self.machined.pool_limit()?),
+        })
+    }
+
+    fn set_pool_limit(&self, size: u64) -> Result<(), Error> {
+        Ok(self.machined.set_pool_limit_(size)?)
+    }
+
+    fn clean_pool(&self, mode: &str) -> Result<Vec<(String, u64)>, Error> {
+        Ok(self.machined.clean_pool(mode)?)
+    }
+
+    fn image_details(&self, name: &str) -> Result<ImageDetails, Error> {
+        // os-release is mandatory, the rest is often just not there
+        Ok(ImageDetails {
+            os_release: self.machined.get_image_osrelease(name)?,
+            hostname: self.machined.get_image_hostname(name).ok().filter(|h| !h.is_empty()),
+            machine_id: self.machined.get_image_machine_id(name).ok().filter(|id| !id.is_empty()).map(|id| hex(&id)),
+            machine_info: self.machined.get_image_machine_info(name).unwrap_or_default(),
+            limit: self.image_limit(name).ok().filter(|&l| l != u64::MAX),
+        })
+    }
+
+    fn machine_details(&self, machine: &Machine) -> Result<MachineDetails, Error> {
+        let m = self.conn.with_proxy("org.freedesktop.machine1", machine.path.clone(), Duration::from_millis(5000));
+        let iface = "org.freedesktop.machine1.Machine";
+        let id: Vec<u8> = m.get(iface, "Id")?;
+        Ok(MachineDetails {
+            class: m.get(iface, "Class")?,
+            id: hex(&id),
+            leader: m.get(iface, "Leader")?,
+            service: m.get(iface, "Service")?,
+            unit: m.get(iface, "Unit")?,
+            addresses: self.machined.get_machine_addresses(&machine.name)?.iter().filter_map(|a| decode_address(a.0, &a.1)).collect(),
+            // not every container has os-release
+            os_release: self.machined.get_machine_osrelease(&machine.name).unwrap_or_default(),
+        })
+    }
+
+    fn unit_processes(&self, unit: &str) -> Result<Vec<Process>, Error> {
+        Ok(self.systemd.get_unit_processes(unit)?.into_iter().map(|p| Process { cgroup: p.0, pid: p.1, command: p.2 }).collect())
+    }
+
+    fn kill_unit(&self, unit: &str, whom: &str, signal: i32) -> Result<(), Error> {
+        Ok(self.systemd.kill_unit(unit, whom, signal)?)
+    }
+
+    // the pids are the ones of the host, no need to go through systemd.
+    // Whole machines go through KillUnit, which polkit may allow
+    fn kill_process(&self, pid: u32, signal: i32) -> Result<(), Error> {
+        if unsafe { libc::kill(pid as libc::pid_t, signal) } < 0 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::EPERM) {
+                return Err(Error::AccessDenied(format!("process {} belongs to another user", pid)));
+            }
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
+    // the unit's object path is derived from its name, no GetUnit needed. The
+    // accounting lives on the Service or Scope interface and FreezerState on
+    // Unit, GetAll without an interface returns those of all of them in one call
+    fn unit_usage(&self, unit: &str) -> Result<Usage, Error> {
+        let path = format!("/org/freedesktop/systemd1/unit/{}", bus_label(unit));
+        let props = self.conn.with_proxy("org.freedesktop.systemd1", path, Duration::from_millis(5000)).get_all("")?;
+        // u64::MAX means the accounting is off
+        let get = |property: &str| props.get(property).and_then(|v| v.0.as_u64()).filter(|&v| v != u64::MAX);
+        let io = match (get("IOReadBytes"), get("IOWriteBytes")) {
+            (Some(r), Some(w)) => Some(r + w),
+            (r, w) => r.or(w),
+        };
+        // one of running, freezing, frozen and thawing, older systemd has no freezer
+        let state = props.get("FreezerState").and_then(|v| v.0.as_str()).unwrap_or_default();
+        Ok(Usage { memory: get("MemoryCurrent"), cpu: get("CPUUsageNSec"), tasks: get("TasksCurrent"), io, frozen: state == "frozen" || state == "freezing" })
+    }
+
+    fn import_image(&self, format: ImportFormat, source: &str, name: &str, force: bool, read_only: bool) -> Result<Transfer, Error> {
+        let fd = unsafe { OwnedFd::new(File::open(source)?.into_raw_fd()) };
+        let (id, path) = match format {
+            ImportFormat::Tar => self.importd.import_tar(fd, name, force, read_only)?,
+            ImportFormat::Raw => self.importd.import_raw(fd, name, force, read_only)?,
+            ImportFormat::Directory => self.importd.import_file_system(fd, name, force, read_only)?,
+        };
+        let kind = match format {
+            ImportFormat::Tar => "import-tar",
+            ImportFormat::Raw => "import-raw",
+            ImportFormat::Directory => "import-fs",
+        };
+        Ok(Transfer { id, path, kind: kind.to_string(), local: name.to_string(), progress: None })
+    }
+
+    // importd reports a negative progress while it doesn't know
+    fn list_transfers(&self) -> Result<Vec<Transfer>, Error> {
+        Ok(self.importd.list_transfers()?.into_iter().map(|t| {
+            Transfer { id: t.0, kind: t.1, local: t.3, progress: Some(t.4).filter(|&p| p >= 0.0), path: t.5 }
+        }).collect())
+    }
+
+    fn transfer_progress(&self, transfer: &Transfer) -> Result<f64, Error> {
+        let t = self.conn.with_proxy("org.freedesktop.import1", transfer.path.clone(), Duration::from_millis(5000));
+        Ok(t.get("org.freedesktop.import1.Transfer", "Progress")?)
+    }
+
+    fn spawn(&self, task: Task) {
+        let tx = match *self.events.borrow() {
+            Some(ref tx) => tx.clone(),
+            None => return,
+        };
+        thread::spawn(move || {
+            // the main connection is owned by the UI thread
+            let result = Connection::new_system().map_err(Error::from).and_then(|conn| {
+                task.run(&DbusBackend::with_timeout(&conn, TASK_TIMEOUT))
+            });
+            let _ = tx.send(BusEvent::TaskDone(task, result));
+        });
+    }
+}
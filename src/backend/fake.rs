@@ -0,0 +1,401 @@
+// in-memory stand-in for machined/systemd, used by the tests
+use dbus;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::File;
+
+use error::Error;
+use super::{MachineBackend,BusEvent,Task,BindMount,Machine,MachineDetails,Image,ImageDetails,Pool,Process,Usage,StartOptions,ImportFormat,Transfer,bus_label,unit_name,transient_unit_name};
+
+pub struct FakeBackend {
+    pub images: RefCell<Vec<Image>>,
+    pub machines: RefCell<Vec<Machine>>,
+    // every mutating call, formatted like "kill foo leader 38"
+    pub calls: RefCell<Vec<String>>,
+    // makes the listing calls fail
+    pub fail: RefCell<Option<Error>>,
+    // results of spawned tasks, to be fed to App::handle_bus
+    pub events: RefCell<Vec<BusEvent>>,
+    pub pool_limit: RefCell<Option<u64>>,
+    // images with autostart
+    pub enabled: RefCell<Vec<String>>,
+    pub frozen: RefCell<Vec<String>>,
+    // of all units, picked by cgroup
+    pub processes: RefCell<Vec<Process>>,
+    // by unit
+    pub usage: RefCell<HashMap<String, Usage>>,
+    // machines started with options, by name
+    pub transient: RefCell<HashMap<String, StartOptions>>,
+    // of importd, set the progress to move them along
+    pub transfers: RefCell<Vec<Transfer>>,
+    // where the images are
+    pub pool_dir: RefCell<String>,
+}
+
+fn no_such_image(name: &str) -> Error {
+    Error::NoSuchImage(format!("No image '{}' known", name))
+}
+
+impl FakeBackend {
+
+    pub fn new() -> FakeBackend {
+        FakeBackend { images: RefCell::new(Vec::new()), machines: RefCell::new(Vec::new()), calls: RefCell::new(Vec::new()), fail: RefCell::new(None), events: RefCell::new(Vec::new()), pool_limit: RefCell::new(None), enabled: RefCell::new(Vec::new()), frozen: RefCell::new(Vec::new()), processes: RefCell::new(Vec::new()), usage: RefCell::new(HashMap::new()), transient: RefCell::new(HashMap::new()), transfers: RefCell::new(Vec::new()), pool_dir: RefCell::new("/var/lib/machines".to_string()) }
+    }
+
+    pub fn with_images(names: &[&str]) -> FakeBackend {
+        let fake = FakeBackend::new();
+        for name in names {
+            fake.add_image(name, false, 1<<30);
+        }
+        fake
+    }
+
+    pub fn add_image(&self, name: &str, ro: bool, size: u64) {
+        self.images.borrow_mut().push(Image {
+            name: name.to_string(),
+            t: "directory".to_string(),
+            ro,
+            t_created: 0,
+            t_modified: 0,
+            size,
+            path: dbus::Path::new(format!("/org/freedesktop/machine1/image/{}", bus_label(name))).unwrap(),
+            machine: None,
+            autostart: false,
+            frozen: false,
+            usage: None,
+            cpu_percent: None,
+            source: None,
+        });
+    }
+
+    pub fn run(&self, name: &str) {
+        self.machines.borrow_mut().push(Machine {
+            name: name.to_string(),
+            class: "container".to_string(),
+            id: "0123456789abcdef0123456789abcdef".to_string(),
+            path: dbus::Path::new(format!("/org/freedesktop/machine1/machine/{}", bus_label(name))).unwrap(),
+        });
+    }
+
+    pub fn add_process(&self, cgroup: &str, pid: u32, command: &str) {
+        self.processes.borrow_mut().push(Process { cgroup: cgroup.to_string(), pid, command: command.to_string() });
+    }
+
+    pub fn stop(&self, name: &str) {
+        self.machines.borrow_mut().retain(|m| m.name != name);
+    }
+
+    pub fn take_events(&self) -> Vec<BusEvent> {
+        self.events.borrow_mut().drain(..).collect()
+    }
+
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.borrow().clone()
+    }
+
+    fn record(&self, call: String) {
+        self.calls.borrow_mut().push(call);
+    }
+
+    fn check_running(&self, name: &str) -> Result<(), Error> {
+        if !self.machines.borrow().iter().any(|m| m.name == name) {
+            return Err(Error::NoSuchMachine(format!("No machine '{}' known", name)));
+        }
+        Ok(())
+    }
+
+    fn has_image(&self, name: &str) -> bool {
+        self.images.borrow().iter().any(|i| i.name == name)
+    }
+}
+
+impl MachineBackend for FakeBackend {
+
+    fn list_images(&self) -> Result<Vec<Image>, Error> {
+        if let Some(ref e) = *self.fail.borrow() {
+            return Err(e.clone());
+        }
+        Ok(self.images.borrow().clone())
+    }
+
+    fn list_machines(&self) -> Result<Vec<Machine>, Error> {
+        if let Some(ref e) = *self.fail.borrow() {
+            return Err(e.clone());
+        }
+        Ok(self.machines.borrow().clone())
+    }
+
+    fn start_machine(&self, name: &str) -> Result<dbus::Path<'static>, Error> {
+        self.record(format!("start {}", name));
+        if !self.has_image(name) {
+            return Err(no_such_image(name));
+        }
+        self.run(name);
+        Ok(dbus::Path::new(format!("/org/freedesktop/systemd1/job/{}", self.calls.borrow().len())).unwrap())
+    }
+
+    fn start_machine_with(&self, name: &str, options: &StartOptions) -> Result<dbus::Path<'static>, Error> {
+        self.record(format!("start {} {}", name, options.args().join(" ")));
+        if options.directory.is_none() && !self.has_image(name) {
+            return Err(no_such_image(name));
+        }
+        self.run(name);
+        self.transient.borrow_mut().insert(name.to_string(), options.clone());
+        Ok(dbus::Path::new(format!("/org/freedesktop/systemd1/job/{}", self.calls.borrow().len())).unwrap())
+    }
+
+    fn machine_unit(&self, name: &str) -> String {
+        if self.transient.borrow().contains_key(name) {
+            transient_unit_name(name)
+        } else {
+            unit_name(name)
+        }
+    }
+
+    fn machine_source(&self, name: &str) -> Option<String> {
+        self.transient.borrow().get(name).and_then(|o| o.source.clone())
+    }
+
+    fn kill_machine(&self, name: &str, who: &str, signal: i32) -> Result<(), Error> {
+        self.record(format!("kill {} {} {}", name, who, signal));
+        if !self.machines.borrow().iter().any(|m| m.name == name) {
+            return Err(Error::NoSuchMachine(format!("No machine '{}' known", name)));
+        }
+        Ok(())
+    }
+
+    fn terminate_machine(&self, name: &str) -> Result<(), Error> {
+        self.record(format!("terminate {}", name));
+        self.check_running(name)?;
+        Ok(())
+    }
+
+    fn open_shell(&self, name: &str, user: &str) -> Result<File, Error> {
+        self.record(format!("shell {} {}", name, user));
+        self.check_running(name)?;
+        Ok(File::open("/dev/null")?)
+    }
+
+    fn open_console(&self, name: &str, login: bool) -> Result<File, Error> {
+        self.record(format!("console {} {}", name, if login { "login" } else { "pty" }));
+        self.check_running(name)?;
+        Ok(File::open("/dev/null")?)
+    }
+
+    fn open_root_directory(&self, name: &str) -> Result<File, Error> {
+        self.record(format!("root {}", name));
+        self.check_running(name)?;
+        Ok(File::open("/")?)
+    }
+
+    fn bind_mount(&self, name: &str, bind: &BindMount) -> Result<(), Error> {
+        self.record(format!("bind {} {}", name, bind.spec()));
+        self.check_running(name)
+    }
+
+    fn autostart(&self) -> Result<Vec<String>, Error> {
+        Ok(self.enabled.borrow().clone())
+    }
+
+    fn set_autostart(&self, name: &str, enable: bool) -> Result<(), Error> {
+        self.record(format!("{} {}", if enable { "enable" } else { "disable" }, name));
+        if !self.has_image(name) {
+            return Err(no_such_image(name));
+        }
+        self.enabled.borrow_mut().retain(|n| n != name);
+        if enable {
+            self.enabled.borrow_mut().push(name.to_string());
+        }
+        Ok(())
+    }
+
+    fn freeze_machine(&self, name: &str) -> Result<(), Error> {
+        self.record(format!("freeze {}", name));
+        self.check_running(name)?;
+        self.frozen.borrow_mut().push(name.to_string());
+        Ok(())
+    }
+
+    fn thaw_machine(&self, name: &str) -> Result<(), Error> {
+        self.record(format!("thaw {}", name));
+        self.check_running(name)?;
+        self.frozen.borrow_mut().retain(|n| n != name);
+        Ok(())
+    }
+
+    fn copy_to_machine(&self, name: &str, source: &str, destination: &str, replace: bool) -> Result<(), Error> {
+        self.record(format!("copy-to {} {} {} {}", name, source, destination, replace));
+        self.check_running(name)
+    }
+
+    fn copy_from_machine(&self, name: &str, source: &str, destination: &str, replace: bool) -> Result<(), Error> {
+        self.record(format!("copy-from {} {} {} {}", name, source, destination, replace));
+        self.check_running(name)
+    }
+
+    fn clone_image(&self, name: &str, new_name: &str, read_only: bool) -> Result<(), Error> {
+        self.record(format!("clone {} {} {}", name, new_name, read_only));
+        let img = self.images.borrow().iter().find(|i| i.name == name).cloned();
+        match img {
+            Some(img) => {
+                self.add_image(new_name, read_only, img.size);
+                Ok(())
+            },
+            None => Err(no_such_image(name)),
+        }
+    }
+
+    fn rename_image(&self, name: &str, new_name: &str) -> Result<(), Error> {
+        self.record(format!("rename {} {}", name, new_name));
+        match self.images.borrow_mut().iter_mut().find(|i| i.name == name) {
+            Some(img) => {
+                img.name = new_name.to_string();
+                Ok(())
+            },
+            None => Err(no_such_image(name)),
+        }
+    }
+
+    fn remove_image(&self, name: &str) -> Result<(), Error> {
+        self.record(format!("remove {}", name));
+        if !self.has_image(name) {
+            return Err(no_such_image(name));
+        }
+        self.images.borrow_mut().retain(|i| i.name != name);
+        Ok(())
+    }
+
+    fn mark_image_read_only(&self, name: &str, read_only: bool) -> Result<(), Error> {
+        self.record(format!("ro {} {}", name, read_only));
+        match self.images.borrow_mut().iter_mut().find(|i| i.name == name) {
+            Some(img) => {
+                img.ro = read_only;
+                Ok(())
+            },
+            None => Err(no_such_image(name)),
+        }
+    }
+
+    fn set_image_limit(&self, name: &str, size: u64) -> Result<(), Error> {
+        self.record(format!("limit {} {}", name, size));
+        if !self.has_image(name) {
+            return Err(no_such_image(name));
+        }
+        Ok(())
+    }
+
+    fn image_path(&self, name: &str) -> Result<String, Error> {
+        if !self.has_image(name) {
+            return Err(no_such_image(name));
+        }
+        Ok(format!("{}/{}", self.pool_dir.borrow(), name))
+    }
+
+    fn pool(&self) -> Result<Pool, Error> {
+        Ok(Pool {
+            path: self.pool_dir.borrow().clone(),
+            usage: Some(self.images.borrow().iter().map(|i| i.size).sum()),
+            limit: *self.pool_limit.borrow(),
+        })
+    }
+
+    fn set_pool_limit(&self, size: u64) -> Result<(), Error> {
+        self.record(format!("pool limit {}", size));
+        *self.pool_limit.borrow_mut() = if size == u64::MAX { None } else { Some(size) };
+        Ok(())
+    }
+
+    fn clean_pool(&self, mode: &str) -> Result<Vec<(String, u64)>, Error> {
+        self.record(format!("clean {}", mode));
+        let machines = self.machines.borrow();
+        let mut images = self.images.borrow_mut();
+        let (removed, kept): (Vec<Image>, Vec<Image>) = images.drain(..).partition(|i| {
+            (mode == "all" || i.name.starts_with('.')) && !machines.iter().any(|m| m.name == i.name)
+        });
+        *images = kept;
+        Ok(removed.into_iter().map(|i| (i.name, i.size)).collect())
+    }
+
+    fn image_details(&self, name: &str) -> Result<ImageDetails, Error> {
+        self.record(format!("details {}", name));
+        if !self.has_image(name) {
+            return Err(no_such_image(name));
+        }
+        let mut os_release = HashMap::new();
+        os_release.insert("PRETTY_NAME".to_string(), format!("{} Linux", name));
+        Ok(ImageDetails { os_release, hostname: Some(name.to_string()), ..Default::default() })
+    }
+
+    fn machine_details(&self, machine: &Machine) -> Result<MachineDetails, Error> {
+        self.record(format!("machine {}", machine.name));
+        Ok(MachineDetails {
+            class: machine.class.clone(),
+            id: machine.id.clone(),
+            leader: 4711,
+            unit: self.machine_unit(&machine.name),
+            addresses: vec!["10.0.0.2".parse().unwrap()],
+            ..Default::default()
+        })
+    }
+
+    fn unit_processes(&self, unit: &str) -> Result<Vec<Process>, Error> {
+        Ok(self.processes.borrow().iter().filter(|p| p.cgroup.contains(unit)).cloned().collect())
+    }
+
+    fn kill_unit(&self, unit: &str, whom: &str, signal: i32) -> Result<(), Error> {
+        self.record(format!("kill-unit {} {} {}", unit, whom, signal));
+        Ok(())
+    }
+
+    fn kill_process(&self, pid: u32, signal: i32) -> Result<(), Error> {
+        self.record(format!("kill-pid {} {}", pid, signal));
+        if !self.processes.borrow().iter().any(|p| p.pid == pid) {
+            return Err(Error::Io(format!("No such process {}", pid)));
+        }
+        Ok(())
+    }
+
+    // no accounting unless a test sets some up
+    fn unit_usage(&self, unit: &str) -> Result<Usage, Error> {
+        let name = self.machines.borrow().iter().map(|m| m.name.clone()).find(|n| self.machine_unit(n) == unit)
+            .ok_or_else(|| Error::NoSuchUnit(format!("Unit {} not loaded.", unit)))?;
+        let mut usage = self.usage.borrow().get(unit).cloned().unwrap_or_default();
+        usage.frozen = self.frozen.borrow().contains(&name);
+        Ok(usage)
+    }
+
+    fn import_image(&self, format: ImportFormat, source: &str, name: &str, force: bool, read_only: bool) -> Result<Transfer, Error> {
+        let kind = match format {
+            ImportFormat::Tar => "import-tar",
+            ImportFormat::Raw => "import-raw",
+            ImportFormat::Directory => "import-fs",
+        };
+        self.record(format!("{} {} {} force={} ro={}", kind, source, name, force, read_only));
+        let id = self.transfers.borrow().len() as u32 + 1;
+        let transfer = Transfer {
+            id,
+            path: dbus::Path::new(format!("/org/freedesktop/import1/transfer/_{}", id)).unwrap(),
+            kind: kind.to_string(),
+            local: name.to_string(),
+            progress: None,
+        };
+        self.transfers.borrow_mut().push(transfer.clone());
+        Ok(transfer)
+    }
+
+    fn list_transfers(&self) -> Result<Vec<Transfer>, Error> {
+        Ok(self.transfers.borrow().clone())
+    }
+
+    fn transfer_progress(&self, transfer: &Transfer) -> Result<f64, Error> {
+        match self.transfers.borrow().iter().find(|t| t.id == transfer.id) {
+            Some(t) => Ok(t.progress.unwrap_or(-1.0)),
+            None => Err(Error::Bus { name: "org.freedesktop.DBus.Error.UnknownObject".to_string(), message: format!("Unknown object '{}'.", transfer.path) }),
+        }
+    }
+
+    fn spawn(&self, task: Task) {
+        let result = task.run(self);
+        self.events.borrow_mut().push(BusEvent::TaskDone(task, result));
+    }
+}
@@ -0,0 +1,55 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+fn common_prefix(a: &str, b: &str) -> String {
+    a.chars().zip(b.chars()).take_while(|&(x, y)| x == y).map(|(x, _)| x).collect()
+}
+
+// complete the last component of input, looked up below root even if input
+// is absolute. Returns the new input and the candidates if it is ambiguous
+pub fn complete_path(root: &Path, input: &str) -> io::Result<(String, Vec<String>)> {
+    let (dir, prefix) = match input.rfind('/') {
+        Some(i) => input.split_at(i + 1),
+        None => ("", input),
+    };
+    let mut names: Vec<(String, bool)> = Vec::new();
+    for entry in fs::read_dir(root.join(dir.trim_start_matches('/')))? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        // like shells, hidden files only if asked for
+        if name.starts_with(prefix) && (prefix.starts_with('.') || !name.starts_with('.')) {
+            let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+            names.push((name, is_dir));
+        }
+    }
+    names.sort();
+    match names.len() {
+        0 => Ok((input.to_string(), Vec::new())),
+        1 => Ok((format!("{}{}{}", dir, names[0].0, if names[0].1 { "/" } else { "" }), Vec::new())),
+        _ => {
+            let common = names[1..].iter().fold(names[0].0.clone(), |acc, n| common_prefix(&acc, &n.0));
+            Ok((format!("{}{}", dir, common), names.into_iter().map(|n| n.0).collect()))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testdir::TempDir;
+
+    #[test]
+    fn complete() {
+        let root = TempDir::new("complete");
+        fs::create_dir_all(root.join("etc/alpha")).unwrap();
+        fs::write(root.join("etc/alpine"), "").unwrap();
+        fs::write(root.join("etc/.hidden"), "").unwrap();
+        assert_eq!(complete_path(&root, "/e").unwrap(), ("/etc/".to_string(), Vec::new()));
+        assert_eq!(complete_path(&root, "/etc/a").unwrap(), ("/etc/alp".to_string(), vec!["alpha".to_string(), "alpine".to_string()]));
+        assert_eq!(complete_path(&root, "etc/alpha").unwrap(), ("etc/alpha/".to_string(), Vec::new()));
+        assert_eq!(complete_path(&root, "/etc/.").unwrap().0, "/etc/.hidden");
+        assert_eq!(complete_path(&root, "/etc/x").unwrap().0, "/etc/x");
+        assert!(complete_path(&root, "/nope/").is_err());
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use backend::BindMount;
+
+// what mat remembers between runs, kept in $XDG_CONFIG_HOME/mat/config
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct Config {
+    // user to open a shell as, per image
+    pub shell_users: BTreeMap<String, String>,
+    // to apply again after the machine was started
+    pub binds: BTreeMap<String, Vec<BindMount>>,
+}
+
+impl Config {
+
+    fn path() -> Option<PathBuf> {
+        env::var_os("XDG_CONFIG_HOME").map(PathBuf::from).filter(|p| p.is_absolute())
+            .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+            .map(|dir| dir.join("mat").join("config"))
+    }
+
+    pub fn load() -> io::Result<Config> {
+        let path = match Config::path() {
+            Some(path) => path,
+            None => return Ok(Config::default()),
+        };
+        match fs::read_to_string(path) {
+            Ok(text) => Ok(Config::parse(&text)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = match Config::path() {
+            Some(path) => path,
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, "neither XDG_CONFIG_HOME nor HOME set")),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.serialize())
+    }
+
+    // ini style, image names are the keys. Unknown sections are dropped
+    pub fn parse(text: &str) -> Config {
+        let mut config = Config::default();
+        let mut section = "";
+        for line in text.lines().map(|l| l.trim()) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                section = &line[1..line.len()-1];
+                continue;
+            }
+            let (key, value) = match line.find('=') {
+                Some(i) => (line[..i].trim().to_string(), line[i+1..].trim().to_string()),
+                None => continue,
+            };
+            match section {
+                "shell" => { config.shell_users.insert(key, value); },
+                // one line per bind mount
+                "bind" => if let Some(b) = BindMount::parse(&value) {
+                    config.binds.entry(key).or_default().push(b);
+                },
+                _ => {},
+            }
+        }
+        config
+    }
+
+    pub fn serialize(&self) -> String {
+        let mut text = String::new();
+        if !self.shell_users.is_empty() {
+            text += "[shell]\n";
+            for (image, user) in &self.shell_users {
+                text += &format!("{}={}\n", image, user);
+            }
+        }
+        if !self.binds.is_empty() {
+            text += "[bind]\n";
+            for (image, binds) in &self.binds {
+                for b in binds {
+                    text += &format!("{}={}\n", image, b.spec());
+                }
+            }
+        }
+        text
+    }
+}
+
+// the user who started mat, even through sudo
+pub fn invoking_user() -> String {
+    env::var("SUDO_USER").or_else(|_| env::var("USER")).unwrap_or_else(|_| "root".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let text = "# comment\n[shell]\nleap = root\ntumbleweed=ludwig\n[other]\nfoo=bar\n[bind]\nleap=/src:/src:ro\nleap=/home/ludwig:/home/ludwig\n";
+        let config = Config::parse(text);
+        assert_eq!(config.shell_users.get("leap").unwrap(), "root");
+        assert_eq!(config.shell_users.len(), 2);
+        assert_eq!(config.binds.get("leap").unwrap().len(), 2);
+        assert!(config.binds.get("leap").unwrap()[0].read_only);
+        assert_eq!(Config::parse(&config.serialize()), config);
+    }
+}
@@ -0,0 +1,57 @@
+use std::fs::File;
+use std::io::{self,Read,Write};
+use std::os::unix::io::AsRawFd;
+
+use pty;
+use vt::Screen;
+
+// a terminal of a running machine, shown in a tab below the list
+pub struct Console {
+    pub name: String,
+    // "login" or "pty"
+    pub kind: String,
+    pub screen: Screen,
+    pub pty: File,
+    // the machine side hung up, kept until the user closes the tab
+    pub exited: bool,
+}
+
+impl Console {
+
+    pub fn new(name: &str, kind: &str, pty: File, size: (usize, usize)) -> Console {
+        pty::set_window_size(pty.as_raw_fd(), size.0, size.1);
+        Console { name: name.to_string(), kind: kind.to_string(), screen: Screen::new(size.0, size.1), pty, exited: false }
+    }
+
+    pub fn resize(&mut self, size: (usize, usize)) {
+        pty::set_window_size(self.pty.as_raw_fd(), size.0, size.1);
+        self.screen.resize(size.0, size.1);
+    }
+
+    // feed what the machine wrote to the screen, false once it hung up
+    pub fn read(&mut self) -> bool {
+        let mut buf = [0u8; 4096];
+        match self.pty.read(&mut buf) {
+            Ok(n) if n > 0 => {
+                self.screen.feed(&buf[..n]);
+                if !self.screen.replies.is_empty() {
+                    let replies: Vec<u8> = self.screen.replies.drain(..).collect();
+                    self.write(&replies);
+                }
+                true
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => true,
+            // EOF or EIO
+            _ => {
+                self.exited = true;
+                false
+            },
+        }
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        if self.pty.write_all(bytes).is_err() {
+            self.exited = true;
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use dbus;
+use std::fmt;
+use std::io;
+
+const POLKIT_HINT: &str = "See https://en.opensuse.org/Systemd-machined for the polkit rules needed.";
+
+// D-Bus errors mat knows how to explain, the String is the message sent by the service
+#[derive(Debug,Clone,PartialEq)]
+pub enum Error {
+    AccessDenied(String),
+    AuthorizationRequired(String),
+    NoSuchUnit(String),
+    NoSuchImage(String),
+    NoSuchMachine(String),
+    ServiceUnknown(String),
+    Bus { name: String, message: String },
+    // local failures, e.g. talking to a pty
+    Io(String),
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e.to_string())
+    }
+}
+
+impl From<dbus::Error> for Error {
+    fn from(e: dbus::Error) -> Error {
+        let message = e.message().unwrap_or("").to_string();
+        match e.name().unwrap_or("") {
+            "org.freedesktop.DBus.Error.AccessDenied" => Error::AccessDenied(message),
+            "org.freedesktop.DBus.Error.InteractiveAuthorizationRequired" => Error::AuthorizationRequired(message),
+            "org.freedesktop.systemd1.NoSuchUnit" => Error::NoSuchUnit(message),
+            "org.freedesktop.machine1.NoSuchImage" => Error::NoSuchImage(message),
+            "org.freedesktop.machine1.NoSuchMachine" => Error::NoSuchMachine(message),
+            "org.freedesktop.DBus.Error.ServiceUnknown" => Error::ServiceUnknown(message),
+            name => Error::Bus { name: name.to_string(), message },
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::AccessDenied(ref m) => write!(f, "Permission denied: {}\n{}", m, POLKIT_HINT),
+            Error::AuthorizationRequired(ref m) => write!(f, "Authorization required, but mat cannot ask for a password: {}\n{}", m, POLKIT_HINT),
+            Error::NoSuchUnit(ref m) => write!(f, "Unit not found: {}\nIs systemd-container installed?", m),
+            Error::NoSuchImage(ref m) => write!(f, "Image not found: {}", m),
+            Error::NoSuchMachine(ref m) => write!(f, "Machine not running: {}", m),
+            Error::ServiceUnknown(ref m) => write!(f, "Service not available: {}\nIs systemd-machined running?", m),
+            Error::Bus { ref name, ref message } => write!(f, "{} ({})", message, name),
+            Error::Io(ref m) => write!(f, "{}", m),
+        }
+    }
+}
+
+impl ::std::error::Error for Error {}
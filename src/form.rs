@@ -0,0 +1,201 @@
+use notcurses::{Received,Key};
+
+#[derive(Clone,Debug,PartialEq)]
+pub enum Value {
+    Text(String),
+    Toggle(bool),
+    Choice(Vec<String>, usize),
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct Field {
+    pub label: String,
+    pub value: Value,
+    // Tab asks for completion instead of moving on
+    pub complete: bool,
+}
+
+// a small dialog with input fields, key handling only, drawing is done by the UI
+#[derive(Clone,Debug,PartialEq)]
+pub struct Form {
+    pub title: String,
+    pub fields: Vec<Field>,
+    pub focus: usize,
+    // validation error shown below the fields
+    pub message: Option<String>,
+}
+
+#[derive(Debug,PartialEq)]
+pub enum FormEvent {
+    Nothing,
+    Changed,
+    Submit,
+    Cancel,
+    // Tab in a field with completion, the owner fills it in
+    Complete,
+}
+
+impl Form {
+
+    pub fn new(title: &str) -> Form {
+        Form { title: title.to_string(), fields: Vec::new(), focus: 0, message: None }
+    }
+
+    pub fn text(mut self, label: &str, value: &str) -> Form {
+        self.fields.push(Field { label: label.to_string(), value: Value::Text(value.to_string()), complete: false });
+        self
+    }
+
+    // text with completion
+    pub fn path(mut self, label: &str, value: &str) -> Form {
+        self.fields.push(Field { label: label.to_string(), value: Value::Text(value.to_string()), complete: true });
+        self
+    }
+
+    pub fn toggle(mut self, label: &str, value: bool) -> Form {
+        self.fields.push(Field { label: label.to_string(), value: Value::Toggle(value), complete: false });
+        self
+    }
+
+    pub fn choice(mut self, label: &str, options: &[&str], selected: usize) -> Form {
+        let options = options.iter().map(|o| o.to_string()).collect();
+        self.fields.push(Field { label: label.to_string(), value: Value::Choice(options, selected), complete: false });
+        self
+    }
+
+    pub fn get_text(&self, idx: usize) -> &str {
+        match self.fields[idx].value {
+            Value::Text(ref s) => s,
+            _ => "",
+        }
+    }
+
+    pub fn set_text(&mut self, idx: usize, value: &str) {
+        if let Value::Text(ref mut s) = self.fields[idx].value {
+            *s = value.to_string();
+        }
+    }
+
+    pub fn get_toggle(&self, idx: usize) -> bool {
+        match self.fields[idx].value {
+            Value::Toggle(b) => b,
+            _ => false,
+        }
+    }
+
+    pub fn get_choice(&self, idx: usize) -> &str {
+        match self.fields[idx].value {
+            Value::Choice(ref o, i) => &o[i],
+            _ => "",
+        }
+    }
+
+    pub fn handle(&mut self, key: Received) -> FormEvent {
+        let n = self.fields.len();
+        match key {
+            Received::Key(Key::Esc) => return FormEvent::Cancel,
+            Received::Key(Key::Enter) => return FormEvent::Submit,
+            Received::Key(Key::Tab) if n > 0 && self.fields[self.focus].complete => return FormEvent::Complete,
+            Received::Key(Key::Tab) | Received::Key(Key::Down) => {
+                if n > 0 {
+                    self.focus = (self.focus + 1) % n;
+                }
+                return FormEvent::Changed;
+            },
+            Received::Key(Key::Up) => {
+                if n > 0 {
+                    self.focus = (self.focus + n - 1) % n;
+                }
+                return FormEvent::Changed;
+            },
+            _ => {},
+        }
+        if n == 0 {
+            return FormEvent::Nothing;
+        }
+        let changed = match self.fields[self.focus].value {
+            Value::Text(ref mut s) => match key {
+                Received::Key(Key::Backspace) | Received::Char('\x7f') | Received::Char('\x08') => s.pop().is_some(),
+                Received::Char(c) if !c.is_control() => {
+                    s.push(c);
+                    true
+                },
+                _ => false,
+            },
+            Value::Toggle(ref mut b) => match key {
+                Received::Char(' ') | Received::Key(Key::Left) | Received::Key(Key::Right) => {
+                    *b = !*b;
+                    true
+                },
+                _ => false,
+            },
+            Value::Choice(ref o, ref mut i) => match key {
+                Received::Char(' ') | Received::Key(Key::Right) => {
+                    *i = (*i + 1) % o.len();
+                    true
+                },
+                Received::Key(Key::Left) => {
+                    *i = (*i + o.len() - 1) % o.len();
+                    true
+                },
+                _ => false,
+            },
+        };
+        if changed {
+            self.message = None;
+            FormEvent::Changed
+        } else {
+            FormEvent::Nothing
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(form: &mut Form, s: &str) {
+        for c in s.chars() {
+            form.handle(Received::Char(c));
+        }
+    }
+
+    #[test]
+    fn edit_fields() {
+        let mut form = Form::new("t").text("Name", "ab").toggle("Read-only", false).choice("Mode", &["hidden", "all"], 0);
+        form.handle(Received::Key(Key::Backspace));
+        typed(&mut form, "cd");
+        assert_eq!(form.get_text(0), "acd");
+        form.handle(Received::Key(Key::Tab));
+        form.handle(Received::Char(' '));
+        assert!(form.get_toggle(1));
+        form.handle(Received::Key(Key::Down));
+        form.handle(Received::Key(Key::Left));
+        assert_eq!(form.get_choice(2), "all");
+        form.handle(Received::Key(Key::Tab));
+        assert_eq!(form.focus, 0);
+        assert_eq!(form.handle(Received::Key(Key::Up)), FormEvent::Changed);
+        assert_eq!(form.focus, 2);
+    }
+
+    #[test]
+    fn submit_and_cancel() {
+        let mut form = Form::new("t").text("Name", "");
+        // keys bound in the list are plain text here
+        assert_eq!(form.handle(Received::Char('q')), FormEvent::Changed);
+        assert_eq!(form.get_text(0), "q");
+        assert_eq!(form.handle(Received::Key(Key::Enter)), FormEvent::Submit);
+        assert_eq!(form.handle(Received::Key(Key::Esc)), FormEvent::Cancel);
+    }
+
+    #[test]
+    fn tab_completes_paths() {
+        let mut form = Form::new("t").path("Path", "/et").toggle("Replace", false);
+        assert_eq!(form.handle(Received::Key(Key::Tab)), FormEvent::Complete);
+        form.set_text(0, "/etc/");
+        assert_eq!(form.get_text(0), "/etc/");
+        // Down still moves on
+        form.handle(Received::Key(Key::Down));
+        assert_eq!(form.focus, 1);
+    }
+}
@@ -0,0 +1,72 @@
+use libc;
+
+pub const SIZE_UNITS: [&str; 5] = ["", "k", "M", "G", "T"];
+
+pub fn format_size(size: u64) -> String {
+    let mut ss = "".to_string();
+    if size > 1<<(10*(SIZE_UNITS.len())) {
+        ss = "-".to_string();
+    } else {
+        for i in (0..SIZE_UNITS.len()).rev() {
+            if size > 1<<(10*i) {
+                ss = format!("{}{}", size>>(10*i), SIZE_UNITS[i]);
+                break;
+            }
+        }
+    }
+    ss
+}
+
+// inverse of format_size, e.g. "20G" or "1.5T", binary units like SIZE_UNITS
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number.parse().map_err(|_| format!("'{}' is not a size", s))?;
+    let unit = unit.trim();
+    // allow the common spellings 20G, 20GB and 20GiB
+    let unit = unit.strip_suffix("iB").or_else(|| unit.strip_suffix('B')).unwrap_or(unit);
+    let exp = match SIZE_UNITS.iter().position(|u| u.eq_ignore_ascii_case(unit)) {
+        Some(exp) => exp,
+        None => return Err(format!("unknown unit '{}', use one of {}", unit, SIZE_UNITS[1..].join(", "))),
+    };
+    let size = number * (1u64 << (10*exp)) as f64;
+    if size >= u64::MAX as f64 {
+        return Err(format!("{} is too large", s));
+    }
+    Ok(size as u64)
+}
+
+// machined timestamps are µs since the epoch, shown in local time
+pub fn format_time(usec: u64) -> String {
+    if usec == 0 {
+        return "-".to_string();
+    }
+    let t = (usec / 1_000_000) as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&t, &mut tm) }.is_null() {
+        return "-".to_string();
+    }
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday, tm.tm_hour, tm.tm_min)
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("20G"), Ok(20 << 30));
+        assert_eq!(parse_size("20 GiB"), Ok(20 << 30));
+        assert_eq!(parse_size("1.5t"), Ok(3 << 39));
+        assert_eq!(parse_size("512"), Ok(512));
+        assert!(parse_size("").is_err());
+        assert!(parse_size("20X").is_err());
+        assert!(parse_size("G").is_err());
+        assert_eq!(format_size(parse_size("20G").unwrap()), "20G");
+    }
+}
@@ -0,0 +1,196 @@
+// This code was autogenerated with `dbus-codegen-rust --file /usr/share/dbus-1/interfaces/org.freedesktop.import1.Manager.xml`, see https://github.com/diwic/dbus-rs
+use dbus as dbus;
+#[allow(unused_imports)]
+use dbus::arg;
+use dbus::blocking;
+
+pub trait OrgFreedesktopDBusPeer {
+    fn ping(&self) -> Result<(), dbus::Error>;
+    fn get_machine_id(&self) -> Result<String, dbus::Error>;
+}
+
+impl<'a, T: blocking::BlockingSender, C: ::std::ops::Deref<Target=T>> OrgFreedesktopDBusPeer for blocking::Proxy<'a, C> {
+
+    fn ping(&self) -> Result<(), dbus::Error> {
+        self.method_call("org.freedesktop.DBus.Peer", "Ping", ())
+    }
+
+    fn get_machine_id(&self) -> Result<String, dbus::Error> {
+        self.method_call("org.freedesktop.DBus.Peer", "GetMachineId", ())
+            .and_then(|r: (String, )| Ok(r.0, ))
+    }
+}
+
+pub trait OrgFreedesktopDBusIntrospectable {
+    fn introspect(&self) -> Result<String, dbus::Error>;
+}
+
+impl<'a, T: blocking::BlockingSender, C: ::std::ops::Deref<Target=T>> OrgFreedesktopDBusIntrospectable for blocking::Proxy<'a, C> {
+
+    fn introspect(&self) -> Result<String, dbus::Error> {
+        self.method_call("org.freedesktop.DBus.Introspectable", "Introspect", ())
+            .and_then(|r: (String, )| Ok(r.0, ))
+    }
+}
+
+pub trait OrgFreedesktopDBusProperties {
+    fn get(&self, interface_name: &str, property_name: &str) -> Result<arg::Variant<Box<dyn arg::RefArg + 'static>>, dbus::Error>;
+    fn get_all(&self, interface_name: &str) -> Result<arg::PropMap, dbus::Error>;
+    fn set(&self, interface_name: &str, property_name: &str, value: arg::Variant<Box<dyn arg::RefArg>>) -> Result<(), dbus::Error>;
+}
+
+#[derive(Debug)]
+pub struct OrgFreedesktopDBusPropertiesPropertiesChanged {
+    pub interface_name: String,
+    pub changed_properties: arg::PropMap,
+    pub invalidated_properties: Vec<String>,
+}
+
+impl arg::AppendAll for OrgFreedesktopDBusPropertiesPropertiesChanged {
+    fn append(&self, i: &mut arg::IterAppend) {
+        arg::RefArg::append(&self.interface_name, i);
+        arg::RefArg::append(&self.changed_properties, i);
+        arg::RefArg::append(&self.invalidated_properties, i);
+    }
+}
+
+impl arg::ReadAll for OrgFreedesktopDBusPropertiesPropertiesChanged {
+    fn read(i: &mut arg::Iter) -> Result<Self, arg::TypeMismatchError> {
+        Ok(OrgFreedesktopDBusPropertiesPropertiesChanged {
+            interface_name: i.read()?,
+            changed_properties: i.read()?,
+            invalidated_properties: i.read()?,
+        })
+    }
+}
+
+impl dbus::message::SignalArgs for OrgFreedesktopDBusPropertiesPropertiesChanged {
+    const NAME: &'static str = "PropertiesChanged";
+    const INTERFACE: &'static str = "org.freedesktop.DBus.Properties";
+}
+
+impl<'a, T: blocking::BlockingSender, C: ::std::ops::Deref<Target=T>> OrgFreedesktopDBusProperties for blocking::Proxy<'a, C> {
+
+    fn get(&self, interface_name: &str, property_name: &str) -> Result<arg::Variant<Box<dyn arg::RefArg + 'static>>, dbus::Error> {
+        self.method_call("org.freedesktop.DBus.Properties", "Get", (interface_name, property_name, ))
+            .and_then(|r: (arg::Variant<Box<dyn arg::RefArg + 'static>>, )| Ok(r.0, ))
+    }
+
+    fn get_all(&self, interface_name: &str) -> Result<arg::PropMap, dbus::Error> {
+        self.method_call("org.freedesktop.DBus.Properties", "GetAll", (interface_name, ))
+            .and_then(|r: (arg::PropMap, )| Ok(r.0, ))
+    }
+
+    fn set(&self, interface_name: &str, property_name: &str, value: arg::Variant<Box<dyn arg::RefArg>>) -> Result<(), dbus::Error> {
+        self.method_call("org.freedesktop.DBus.Properties", "Set", (interface_name, property_name, value, ))
+    }
+}
+
+pub trait OrgFreedesktopImport1Manager {
+    fn import_tar(&self, fd: arg::OwnedFd, local_name: &str, force: bool, read_only: bool) -> Result<(u32, dbus::Path<'static>), dbus::Error>;
+    fn import_raw(&self, fd: arg::OwnedFd, local_name: &str, force: bool, read_only: bool) -> Result<(u32, dbus::Path<'static>), dbus::Error>;
+    fn import_file_system(&self, fd: arg::OwnedFd, local_name: &str, force: bool, read_only: bool) -> Result<(u32, dbus::Path<'static>), dbus::Error>;
+    fn export_tar(&self, local_name: &str, fd: arg::OwnedFd, format: &str) -> Result<(u32, dbus::Path<'static>), dbus::Error>;
+    fn export_raw(&self, local_name: &str, fd: arg::OwnedFd, format: &str) -> Result<(u32, dbus::Path<'static>), dbus::Error>;
+    fn pull_tar(&self, url: &str, local_name: &str, verify_mode: &str, force: bool) -> Result<(u32, dbus::Path<'static>), dbus::Error>;
+    fn pull_raw(&self, url: &str, local_name: &str, verify_mode: &str, force: bool) -> Result<(u32, dbus::Path<'static>), dbus::Error>;
+    fn list_transfers(&self) -> Result<Vec<(u32, String, String, String, f64, dbus::Path<'static>)>, dbus::Error>;
+    fn cancel_transfer(&self, transfer_id: u32) -> Result<(), dbus::Error>;
+}
+
+#[derive(Debug)]
+pub struct OrgFreedesktopImport1ManagerTransferNew {
+    pub transfer_id: u32,
+    pub transfer_path: dbus::Path<'static>,
+}
+
+impl arg::AppendAll for OrgFreedesktopImport1ManagerTransferNew {
+    fn append(&self, i: &mut arg::IterAppend) {
+        arg::RefArg::append(&self.transfer_id, i);
+        arg::RefArg::append(&self.transfer_path, i);
+    }
+}
+
+impl arg::ReadAll for OrgFreedesktopImport1ManagerTransferNew {
+    fn read(i: &mut arg::Iter) -> Result<Self, arg::TypeMismatchError> {
+        Ok(OrgFreedesktopImport1ManagerTransferNew {
+            transfer_id: i.read()?,
+            transfer_path: i.read()?,
+        })
+    }
+}
+
+impl dbus::message::SignalArgs for OrgFreedesktopImport1ManagerTransferNew {
+    const NAME: &'static str = "TransferNew";
+    const INTERFACE: &'static str = "org.freedesktop.import1.Manager";
+}
+
+#[derive(Debug)]
+pub struct OrgFreedesktopImport1ManagerTransferRemoved {
+    pub transfer_id: u32,
+    pub transfer_path: dbus::Path<'static>,
+    pub result: String,
+}
+
+impl arg::AppendAll for OrgFreedesktopImport1ManagerTransferRemoved {
+    fn append(&self, i: &mut arg::IterAppend) {
+        arg::RefArg::append(&self.transfer_id, i);
+        arg::RefArg::append(&self.transfer_path, i);
+        arg::RefArg::append(&self.result, i);
+    }
+}
+
+impl arg::ReadAll for OrgFreedesktopImport1ManagerTransferRemoved {
+    fn read(i: &mut arg::Iter) -> Result<Self, arg::TypeMismatchError> {
+        Ok(OrgFreedesktopImport1ManagerTransferRemoved {
+            transfer_id: i.read()?,
+            transfer_path: i.read()?,
+            result: i.read()?,
+        })
+    }
+}
+
+impl dbus::message::SignalArgs for OrgFreedesktopImport1ManagerTransferRemoved {
+    const NAME: &'static str = "TransferRemoved";
+    const INTERFACE: &'static str = "org.freedesktop.import1.Manager";
+}
+
+impl<'a, T: blocking::BlockingSender, C: ::std::ops::Deref<Target=T>> OrgFreedesktopImport1Manager for blocking::Proxy<'a, C> {
+
+    fn import_tar(&self, fd: arg::OwnedFd, local_name: &str, force: bool, read_only: bool) -> Result<(u32, dbus::Path<'static>), dbus::Error> {
+        self.method_call("org.freedesktop.import1.Manager", "ImportTar", (fd, local_name, force, read_only, ))
+    }
+
+    fn import_raw(&self, fd: arg::OwnedFd, local_name: &str, force: bool, read_only: bool) -> Result<(u32, dbus::Path<'static>), dbus::Error> {
+        self.method_call("org.freedesktop.import1.Manager", "ImportRaw", (fd, local_name, force, read_only, ))
+    }
+
+    fn import_file_system(&self, fd: arg::OwnedFd, local_name: &str, force: bool, read_only: bool) -> Result<(u32, dbus::Path<'static>), dbus::Error> {
+        self.method_call("org.freedesktop.import1.Manager", "ImportFileSystem", (fd, local_name, force, read_only, ))
+    }
+
+    fn export_tar(&self, local_name: &str, fd: arg::OwnedFd, format: &str) -> Result<(u32, dbus::Path<'static>), dbus::Error> {
+        self.method_call("org.freedesktop.import1.Manager", "ExportTar", (local_name, fd, format, ))
+    }
+
+    fn export_raw(&self, local_name: &str, fd: arg::OwnedFd, format: &str) -> Result<(u32, dbus::Path<'static>), dbus::Error> {
+        self.method_call("org.freedesktop.import1.Manager", "ExportRaw", (local_name, fd, format, ))
+    }
+
+    fn pull_tar(&self, url: &str, local_name: &str, verify_mode: &str, force: bool) -> Result<(u32, dbus::Path<'static>), dbus::Error> {
+        self.method_call("org.freedesktop.import1.Manager", "PullTar", (url, local_name, verify_mode, force, ))
+    }
+
+    fn pull_raw(&self, url: &str, local_name: &str, verify_mode: &str, force: bool) -> Result<(u32, dbus::Path<'static>), dbus::Error> {
+        self.method_call("org.freedesktop.import1.Manager", "PullRaw", (url, local_name, verify_mode, force, ))
+    }
+
+    fn list_transfers(&self) -> Result<Vec<(u32, String, String, String, f64, dbus::Path<'static>)>, dbus::Error> {
+        self.method_call("org.freedesktop.import1.Manager", "ListTransfers", ())
+            .and_then(|r: (Vec<(u32, String, String, String, f64, dbus::Path<'static>)>, )| Ok(r.0, ))
+    }
+
+    fn cancel_transfer(&self, transfer_id: u32) -> Result<(), dbus::Error> {
+        self.method_call("org.freedesktop.import1.Manager", "CancelTransfer", (transfer_id, ))
+    }
+}
@@ -0,0 +1,604 @@
+// reads journal files directly, see https://systemd.io/JOURNAL_FILE_FORMAT/
+use std::collections::{HashMap,VecDeque};
+use std::ffi::CString;
+use std::fs::{self,File};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::{AsRawFd,FromRawFd};
+use std::path::{Path,PathBuf};
+use std::time::SystemTime;
+
+use libc;
+
+const SIGNATURE: &[u8] = b"LPKSHHRH";
+// shortest header, as of systemd 187
+const MIN_HEADER_SIZE: usize = 208;
+// 32 bit offsets in entries and entry arrays, extra fields in data objects
+const INCOMPATIBLE_COMPACT: u32 = 16;
+
+const OBJECT_HEADER_SIZE: usize = 16;
+const OBJECT_DATA: u8 = 1;
+const OBJECT_ENTRY: u8 = 3;
+const OBJECT_ENTRY_ARRAY: u8 = 6;
+// larger ones are skipped, a field is rarely more than a few kB
+const MAX_OBJECT_SIZE: u64 = 16 << 20;
+// XZ, LZ4 and ZSTD, mat can't decompress any of them
+const OBJECT_COMPRESSED: u8 = 1 | 2 | 4;
+
+// the newest entries a Journal keeps
+const MAX_ENTRIES: usize = 10000;
+// entries looked up in one go when reading backwards
+const BATCH: u64 = 1024;
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct Entry {
+    pub seqnum: u64,
+    // µs since the epoch
+    pub realtime: u64,
+    pub fields: Vec<(String, String)>,
+    // some fields were compressed and are missing
+    pub compressed: bool,
+}
+
+impl Entry {
+
+    pub fn get(&self, field: &str) -> Option<&str> {
+        self.fields.iter().find(|f| f.0 == field).map(|f| f.1.as_str())
+    }
+
+    // syslog priority, entries without one are info
+    pub fn priority(&self) -> u8 {
+        self.get("PRIORITY").and_then(|p| p.parse().ok()).unwrap_or(6)
+    }
+
+    // who logged it, like journalctl shows it
+    pub fn identifier(&self) -> String {
+        let ident = self.get("SYSLOG_IDENTIFIER").or_else(|| self.get("_COMM")).unwrap_or("unknown");
+        match self.get("_PID") {
+            Some(pid) => format!("{}[{}]", ident, pid),
+            None => ident.to_string(),
+        }
+    }
+
+    // journald only compresses large fields, that's likely the message
+    pub fn message(&self) -> &str {
+        self.get("MESSAGE").unwrap_or(if self.compressed { "[compressed, not shown]" } else { "" })
+    }
+}
+
+fn le32(data: &[u8], offset: usize) -> Option<u32> {
+    let b = data.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn le64(data: &[u8], offset: usize) -> Option<u64> {
+    let b = data.get(offset..offset + 8)?;
+    Some(u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+}
+
+// FIELD=value, Err if compressed
+type Field = Result<(String, String), ()>;
+
+// a journal file read with pread into buffers of our own. It may belong to a
+// container that changes it while we read, that just makes reads fail
+struct JournalFile {
+    file: File,
+    len: u64,
+    compact: bool,
+    n_entries: u64,
+    entry_array_offset: u64,
+    // µs since the epoch
+    tail_realtime: u64,
+}
+
+impl JournalFile {
+
+    fn open(file: File) -> Result<JournalFile, String> {
+        let len = file.metadata().map_err(|e| e.to_string())?.len();
+        let mut header = vec![0u8; MIN_HEADER_SIZE];
+        if file.read_exact_at(&mut header, 0).is_err() || &header[..8] != SIGNATURE {
+            return Err("not a journal file".to_string());
+        }
+        let incompatible = le32(&header, 12).unwrap_or(0);
+        Ok(JournalFile {
+            file,
+            len,
+            compact: incompatible & INCOMPATIBLE_COMPACT != 0,
+            n_entries: le64(&header, 152).unwrap_or(0),
+            entry_array_offset: le64(&header, 176).unwrap_or(0),
+            tail_realtime: le64(&header, 192).unwrap_or(0),
+        })
+    }
+
+    fn read(&self, offset: u64, len: u64) -> Option<Vec<u8>> {
+        if offset.checked_add(len)? > self.len {
+            return None;
+        }
+        let mut buf = vec![0u8; len as usize];
+        self.file.read_exact_at(&mut buf, offset).ok()?;
+        Some(buf)
+    }
+
+    // type, flags and the whole object at offset
+    fn object(&self, offset: u64) -> Option<(u8, u8, Vec<u8>)> {
+        let header = self.read(offset, OBJECT_HEADER_SIZE as u64)?;
+        let size = le64(&header, 8)?;
+        if size < OBJECT_HEADER_SIZE as u64 || size > MAX_OBJECT_SIZE {
+            return None;
+        }
+        Some((header[0], header[1], self.read(offset, size)?))
+    }
+
+    fn data(&self, offset: u64) -> Option<Field> {
+        let (t, flags, data) = self.object(offset)?;
+        if t != OBJECT_DATA {
+            return None;
+        }
+        if flags & OBJECT_COMPRESSED != 0 {
+            return Some(Err(()));
+        }
+        let payload = data.get(if self.compact { 72 } else { 64 }..)?;
+        let eq = payload.iter().position(|&b| b == b'=')?;
+        Some(Ok((String::from_utf8_lossy(&payload[..eq]).into_owned(), String::from_utf8_lossy(&payload[eq + 1..]).into_owned())))
+    }
+
+    // data objects are shared between entries, so they are looked up once
+    fn entry(&self, offset: u64, fields: &mut HashMap<u64, Option<Field>>) -> Option<Entry> {
+        let (t, _, data) = self.object(offset)?;
+        if t != OBJECT_ENTRY {
+            return None;
+        }
+        let item_size = if self.compact { 4 } else { 16 };
+        let mut entry = Entry { seqnum: le64(&data, 16)?, realtime: le64(&data, 24)?, fields: Vec::new(), compressed: false };
+        let mut item = 64;
+        while item + item_size <= data.len() {
+            let data_offset = if self.compact { le32(&data, item)? as u64 } else { le64(&data, item)? };
+            match *fields.entry(data_offset).or_insert_with(|| self.data(data_offset)) {
+                Some(Ok(ref field)) => entry.fields.push(field.clone()),
+                Some(Err(())) => entry.compressed = true,
+                None => (),
+            }
+            item += item_size;
+        }
+        Some(entry)
+    }
+
+    // the offsets of the entries from index start to end, from the chain of
+    // entry arrays. Each is twice as large as the one before, so it's short
+    fn entry_offsets(&self, start: u64, end: u64) -> Vec<u64> {
+        let item_size = if self.compact { 4 } else { 8 };
+        let mut offsets = Vec::new();
+        let mut array = self.entry_array_offset;
+        let mut first = 0;
+        while array != 0 && first < end {
+            let header = match self.read(array, 24) {
+                Some(header) => header,
+                None => break,
+            };
+            let (size, next) = (le64(&header, 8).unwrap_or(0), le64(&header, 16).unwrap_or(0));
+            if header[0] != OBJECT_ENTRY_ARRAY || size < 24 || size > self.len {
+                break;
+            }
+            let n = (size - 24) / item_size;
+            let (from, to) = (start.max(first), end.min(first + n));
+            if from < to {
+                let items = match self.read(array + 24 + (from - first) * item_size, (to - from) * item_size) {
+                    Some(items) => items,
+                    None => break,
+                };
+                offsets.extend(items.chunks(item_size as usize).map(|i| if self.compact { le32(i, 0).unwrap_or(0) as u64 } else { le64(i, 0).unwrap_or(0) }));
+            }
+            first += n;
+            array = next;
+        }
+        offsets
+    }
+}
+
+// where reading a file stopped
+#[derive(Debug)]
+struct FileState {
+    id: usize,
+    mtime: SystemTime,
+    len: u64,
+    // entries read, by their index
+    seen: u64,
+}
+
+// the journal files in some directories, of each file only what was appended
+// since the last refresh is read. Only matching entries are kept, the newest
+// MAX_ENTRIES of them
+#[derive(Debug,Default)]
+pub struct Journal {
+    pub dirs: Vec<PathBuf>,
+    // the dirs are in the tree of a machine, resolved as if it was /
+    root: Option<File>,
+    // an entry is kept if one of these FIELD=value pairs matches, or if there are none
+    matches: Vec<(String, String)>,
+    pub limit: usize,
+    files: HashMap<PathBuf, FileState>,
+    next_id: usize,
+    // oldest first, with the id of their file
+    entries: VecDeque<(usize, Entry)>,
+}
+
+// like openat2 with RESOLVE_IN_ROOT does it
+#[repr(C)]
+struct OpenHow {
+    flags: u64,
+    mode: u64,
+    resolve: u64,
+}
+const RESOLVE_IN_ROOT: u64 = 0x10;
+
+// a directory of a machine, symlinks in there don't lead out of its tree
+fn open_in_root(root: &File, path: &Path) -> io::Result<File> {
+    let path = CString::new(path.as_os_str().as_bytes()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let how = OpenHow { flags: (libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC) as u64, mode: 0, resolve: RESOLVE_IN_ROOT };
+    let fd = unsafe { libc::syscall(libc::SYS_openat2, root.as_raw_fd(), path.as_ptr(), &how as *const OpenHow, std::mem::size_of::<OpenHow>()) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { File::from_raw_fd(fd as libc::c_int) })
+}
+
+// a file in dir that is no symlink, and no fifo that would block the open
+fn open_file(dir: &File, name: &std::ffi::OsStr) -> io::Result<File> {
+    let name = CString::new(name.as_bytes()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let fd = unsafe { libc::openat(dir.as_raw_fd(), name.as_ptr(), libc::O_RDONLY | libc::O_CLOEXEC | libc::O_NOFOLLOW | libc::O_NONBLOCK) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+impl Journal {
+
+    pub fn new(dirs: Vec<PathBuf>, matches: Vec<(String, String)>) -> Journal {
+        Journal { dirs, matches, limit: MAX_ENTRIES, ..Default::default() }
+    }
+
+    // dirs are relative to the root directory of a machine
+    pub fn in_root(root: File, dirs: Vec<PathBuf>, matches: Vec<(String, String)>) -> Journal {
+        Journal { root: Some(root), ..Journal::new(dirs, matches) }
+    }
+
+    fn wanted(&self, e: &Entry) -> bool {
+        self.matches.is_empty() || self.matches.iter().any(|m| e.get(&m.0) == Some(&m.1))
+    }
+
+    // the matching entries from index start on. A new file is read from the
+    // end until there are enough, older ones wouldn't be kept anyway
+    fn read(&self, file: &JournalFile, start: u64) -> Vec<Entry> {
+        let mut fields = HashMap::new();
+        let mut entries = Vec::new();
+        if start > 0 {
+            for offset in file.entry_offsets(start, file.n_entries) {
+                if let Some(e) = file.entry(offset, &mut fields).filter(|e| self.wanted(e)) {
+                    entries.push(e);
+                }
+            }
+            return entries;
+        }
+        // entries older than this are dropped right away
+        let floor = if self.entries.len() >= self.limit { self.entries.front().map(|e| (e.1.realtime, e.1.seqnum)) } else { None };
+        let mut end = file.n_entries;
+        'batches: while end > 0 && entries.len() < self.limit {
+            let start = end.saturating_sub(BATCH);
+            for offset in file.entry_offsets(start, end).into_iter().rev() {
+                let e = match file.entry(offset, &mut fields) {
+                    Some(e) => e,
+                    None => continue,
+                };
+                if floor.is_some_and(|f| (e.realtime, e.seqnum) < f) {
+                    break 'batches;
+                }
+                if self.wanted(&e) {
+                    entries.push(e);
+                    if entries.len() == self.limit {
+                        break 'batches;
+                    }
+                }
+            }
+            end = start;
+        }
+        entries.reverse();
+        entries
+    }
+
+    // sorted in among the others, the oldest are dropped beyond the limit
+    fn add(&mut self, id: usize, new: Vec<Entry>) {
+        // usually all of them are newer than what there is, the entries of
+        // an archived file that showed up are merged in
+        let sorted = match (self.entries.back(), new.first()) {
+            (Some(last), Some(first)) => (last.1.realtime, last.1.seqnum) <= (first.realtime, first.seqnum),
+            _ => true,
+        };
+        self.entries.extend(new.into_iter().map(|e| (id, e)));
+        if !sorted {
+            self.entries.make_contiguous().sort_by_key(|e| (e.1.realtime, e.1.seqnum));
+        }
+        let excess = self.entries.len().saturating_sub(self.limit);
+        self.entries.drain(..excess);
+    }
+
+    // true if there is anything new. Files that can't be read are skipped,
+    // journald might be rotating them
+    pub fn refresh(&mut self) -> bool {
+        let mut changed = false;
+        let mut seen = Vec::new();
+        let mut updated = Vec::new();
+        for path in &self.dirs {
+            let opened = match self.root {
+                Some(ref root) => open_in_root(root, path),
+                None => File::open(path),
+            };
+            // listed through the descriptor, the path might lead elsewhere by now
+            let (dir, files) = match opened.and_then(|d| Ok((fs::read_dir(format!("/proc/self/fd/{}", d.as_raw_fd()))?, d))) {
+                Ok((files, d)) => (d, files),
+                Err(_) => continue,
+            };
+            for f in files.filter_map(|f| f.ok()) {
+                let name = f.file_name();
+                let name_str = name.to_string_lossy();
+                // the ~ ones were not closed properly
+                if !(name_str.ends_with(".journal") || name_str.ends_with(".journal~")) {
+                    continue;
+                }
+                let file = match open_file(&dir, &name) {
+                    Ok(file) => file,
+                    Err(_) => continue,
+                };
+                let (mtime, len) = match file.metadata().and_then(|m| Ok((m.is_file(), m.modified()?, m.len()))) {
+                    Ok((true, mtime, len)) => (mtime, len),
+                    _ => continue,
+                };
+                let path = path.join(&name);
+                seen.push(path.clone());
+                if self.files.get(&path).is_some_and(|f| f.mtime == mtime && f.len == len) {
+                    continue;
+                }
+                if let Ok(file) = JournalFile::open(file) {
+                    updated.push((path, file, mtime, len));
+                }
+            }
+        }
+        let ids: Vec<usize> = self.files.iter().filter(|f| !seen.contains(f.0)).map(|f| f.1.id).collect();
+        if !ids.is_empty() {
+            self.files.retain(|path, _| seen.contains(path));
+            self.entries.retain(|e| !ids.contains(&e.0));
+            changed = true;
+        }
+        // the newest first, so older files can stop early
+        updated.sort_by_key(|u| std::cmp::Reverse(u.1.tail_realtime));
+        for (path, file, mtime, len) in updated {
+            let state = match self.files.remove(&path) {
+                // it shrunk, so it's a different file now
+                Some(state) if len < state.len || file.n_entries < state.seen => {
+                    self.entries.retain(|e| e.0 != state.id);
+                    FileState { seen: 0, ..state }
+                },
+                Some(state) => state,
+                None => {
+                    self.next_id += 1;
+                    FileState { id: self.next_id, mtime, len, seen: 0 }
+                },
+            };
+            let entries = self.read(&file, state.seen);
+            changed |= !entries.is_empty() || state.seen == 0;
+            self.add(state.id, entries);
+            self.files.insert(path, FileState { mtime, len, seen: file.n_entries, ..state });
+        }
+        changed
+    }
+
+    // of all files, oldest first
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().map(|e| &e.1)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use testdir::TempDir;
+
+    // writes just enough of the format for the reader, no hash tables
+    pub fn write_journal(path: &Path, compact: bool, entries: &[(u64, &[&str])]) {
+        let header_size = 272;
+        let mut data = vec![0u8; header_size];
+        data[..8].copy_from_slice(SIGNATURE);
+        if compact {
+            data[12] = INCOMPATIBLE_COMPACT as u8;
+        }
+        data[88..96].copy_from_slice(&(header_size as u64).to_le_bytes());
+        let mut data_offsets = HashMap::new();
+        let mut entry_offsets = Vec::new();
+        for (seqnum, &(realtime, fields)) in entries.iter().enumerate() {
+            let mut items = Vec::new();
+            for field in fields {
+                let offset = *data_offsets.entry(field.to_string()).or_insert_with(|| {
+                    let offset = data.len();
+                    let start = if compact { 72 } else { 64 };
+                    data.push(OBJECT_DATA);
+                    data.extend_from_slice(&[0; 7]);
+                    data.extend_from_slice(&((start + field.len()) as u64).to_le_bytes());
+                    data.resize(offset + start, 0);
+                    data.extend_from_slice(field.as_bytes());
+                    data.resize((data.len() + 7) & !7, 0);
+                    offset
+                });
+                items.push(offset);
+            }
+            let offset = data.len();
+            entry_offsets.push(offset);
+            let size = 64 + items.len() * if compact { 4 } else { 16 };
+            data.push(OBJECT_ENTRY);
+            data.extend_from_slice(&[0; 7]);
+            data.extend_from_slice(&(size as u64).to_le_bytes());
+            data.extend_from_slice(&(seqnum as u64 + 1).to_le_bytes());
+            data.extend_from_slice(&realtime.to_le_bytes());
+            data.resize(offset + 64, 0);
+            for item in items {
+                if compact {
+                    data.extend_from_slice(&(item as u32).to_le_bytes());
+                } else {
+                    data.extend_from_slice(&(item as u64).to_le_bytes());
+                    data.extend_from_slice(&[0; 8]);
+                }
+            }
+            data.resize((data.len() + 7) & !7, 0);
+        }
+        // a chain of arrays twice as large as the one before, the last one
+        // only partly filled
+        let (mut capacity, mut next_field) = (2, 176);
+        let mut rest = &entry_offsets[..];
+        while !rest.is_empty() {
+            let offset = data.len();
+            data[next_field..next_field + 8].copy_from_slice(&(offset as u64).to_le_bytes());
+            let size = 24 + capacity * if compact { 4 } else { 8 };
+            data.push(OBJECT_ENTRY_ARRAY);
+            data.extend_from_slice(&[0; 7]);
+            data.extend_from_slice(&(size as u64).to_le_bytes());
+            data.extend_from_slice(&[0; 8]);
+            let (items, more) = rest.split_at(rest.len().min(capacity));
+            for &item in items {
+                if compact {
+                    data.extend_from_slice(&(item as u32).to_le_bytes());
+                } else {
+                    data.extend_from_slice(&(item as u64).to_le_bytes());
+                }
+            }
+            data.resize((offset + size + 7) & !7, 0);
+            next_field = offset + 16;
+            rest = more;
+            capacity *= 2;
+        }
+        data[152..160].copy_from_slice(&(entries.len() as u64).to_le_bytes());
+        let tail = entries.last().map_or(0, |e| e.0);
+        data[192..200].copy_from_slice(&tail.to_le_bytes());
+        // preallocated like journald does it
+        let arena_size = data.len() - header_size + 4096;
+        data[96..104].copy_from_slice(&(arena_size as u64).to_le_bytes());
+        data.resize(header_size + arena_size, 0);
+        fs::write(path, data).unwrap();
+    }
+
+    fn read(dir: &Path) -> Vec<Entry> {
+        let mut journal = Journal::new(vec![dir.to_path_buf()], Vec::new());
+        journal.refresh();
+        journal.entries().cloned().collect()
+    }
+
+    #[test]
+    fn reads_entries() {
+        let dir = TempDir::new("journal-reads");
+        for &compact in &[false, true] {
+            let path = dir.join("system.journal");
+            write_journal(&path, compact, &[
+                (1_000, &["MESSAGE=hello", "PRIORITY=4", "SYSLOG_IDENTIFIER=demo", "_PID=42"]),
+                (2_000, &["MESSAGE=world", "SYSLOG_IDENTIFIER=demo"]),
+            ]);
+            let entries = read(&dir);
+            assert_eq!(entries.len(), 2);
+            assert_eq!(entries[0].message(), "hello");
+            assert_eq!(entries[0].priority(), 4);
+            assert_eq!(entries[0].identifier(), "demo[42]");
+            assert_eq!(entries[1].realtime, 2_000);
+            assert_eq!(entries[1].seqnum, 2);
+            assert_eq!(entries[1].priority(), 6);
+            // the data object of the identifier is shared
+            assert_eq!(entries[1].identifier(), "demo");
+        }
+        fs::write(dir.join("system.journal"), "not a journal").unwrap();
+        assert!(read(&dir).is_empty());
+        // a compressed message
+        let path = dir.join("system.journal");
+        write_journal(&path, false, &[(1_000, &["MESSAGE=hello", "SYSLOG_IDENTIFIER=demo"])]);
+        let mut data = fs::read(&path).unwrap();
+        let payload = data.windows(13).position(|w| w == b"MESSAGE=hello").unwrap();
+        data[payload - 64 + 1] = 4;
+        fs::write(&path, data).unwrap();
+        let entries = read(&dir);
+        assert_eq!(entries[0].identifier(), "demo");
+        assert_eq!(entries[0].message(), "[compressed, not shown]");
+    }
+
+    #[test]
+    fn stays_in_the_root() {
+        let root = TempDir::new("journal-root");
+        let dir = root.join("var/log/journal/abc");
+        fs::create_dir_all(&dir).unwrap();
+        write_journal(&dir.join("system.journal"), true, &[(1, &["MESSAGE=inside"])]);
+        // a file elsewhere on the host, linked to in two ways
+        let host = TempDir::new("journal-host");
+        write_journal(&host.join("system.journal"), true, &[(2, &["MESSAGE=outside"])]);
+        std::os::unix::fs::symlink(host.join("system.journal"), dir.join("linked.journal")).unwrap();
+        std::os::unix::fs::symlink(&*host, root.join("run")).unwrap();
+        let mut journal = Journal::in_root(File::open(&*root).unwrap(), vec![PathBuf::from("var/log/journal/abc"), PathBuf::from("run")], Vec::new());
+        assert!(journal.refresh());
+        let messages: Vec<&str> = journal.entries().map(|e| e.message()).collect();
+        // absolute links resolve inside the root, where they lead nowhere
+        assert_eq!(messages, vec!["inside"]);
+        // and .. stops at the root
+        let mut journal = Journal::in_root(File::open(&*root).unwrap(), vec![Path::new("../../..").join(host.strip_prefix("/").unwrap())], Vec::new());
+        assert!(!journal.refresh());
+    }
+
+    #[test]
+    fn merges_files_and_notices_changes() {
+        let dir = TempDir::new("journal-merge");
+        write_journal(&dir.join("system.journal"), true, &[(1, &["MESSAGE=one"]), (3, &["MESSAGE=three"])]);
+        write_journal(&dir.join("user-1000.journal~"), false, &[(2, &["MESSAGE=two"])]);
+        fs::write(dir.join("README"), "not a journal").unwrap();
+        let mut journal = Journal::new(vec![dir.clone(), dir.join("missing")], Vec::new());
+        assert!(journal.refresh());
+        let messages: Vec<&str> = journal.entries().map(|e| e.message()).collect();
+        assert_eq!(messages, vec!["one", "two", "three"]);
+        assert!(!journal.refresh());
+        fs::remove_file(dir.join("user-1000.journal~")).unwrap();
+        assert!(journal.refresh());
+        assert_eq!(journal.entries().count(), 2);
+    }
+
+    #[test]
+    fn reads_what_was_appended() {
+        let dir = TempDir::new("journal-append");
+        let path = dir.join("system.journal");
+        let mut entries: Vec<(u64, &[&str])> = vec![(1, &["MESSAGE=one", "UNIT=a"]), (2, &["MESSAGE=two", "UNIT=b"])];
+        write_journal(&path, true, &entries);
+        let mut journal = Journal::new(vec![dir.clone()], vec![("UNIT".to_string(), "a".to_string())]);
+        journal.limit = 2;
+        assert!(journal.refresh());
+        assert_eq!(journal.files[&path].seen, 2);
+        entries.push((3, &["MESSAGE=three", "UNIT=a"]));
+        entries.push((4, &["MESSAGE=four", "UNIT=a"]));
+        write_journal(&path, true, &entries);
+        assert!(journal.refresh());
+        assert_eq!(journal.files[&path].seen, 4);
+        let messages: Vec<&str> = journal.entries().map(|e| e.message()).collect();
+        assert_eq!(messages, vec!["three", "four"]);
+        // rewritten shorter, read again from the start
+        write_journal(&path, true, &entries[..1]);
+        assert!(journal.refresh());
+        assert_eq!(journal.entries().map(|e| e.message()).collect::<Vec<_>>(), vec!["one"]);
+    }
+
+    #[test]
+    fn reads_new_files_from_the_end() {
+        let dir = TempDir::new("journal-tail");
+        let messages: Vec<String> = (0..3000).map(|i| format!("MESSAGE={}", i)).collect();
+        let fields: Vec<[&str; 1]> = messages.iter().map(|m| [m.as_str()]).collect();
+        let entries: Vec<(u64, &[&str])> = fields.iter().enumerate().map(|(i, f)| (i as u64 + 100, &f[..])).collect();
+        write_journal(&dir.join("system.journal"), false, &entries);
+        // an older file with nothing newer than what is kept already
+        write_journal(&dir.join("system@old.journal"), false, &entries[..10]);
+        let mut journal = Journal::new(vec![dir.clone()], Vec::new());
+        journal.limit = 1500;
+        assert!(journal.refresh());
+        assert_eq!(journal.entries().count(), 1500);
+        assert_eq!(journal.entries().next().unwrap().message(), "1500");
+        assert_eq!(journal.entries().last().unwrap().message(), "2999");
+    }
+}
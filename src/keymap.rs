@@ -0,0 +1,185 @@
+use notcurses::{Input,Received,Key,Position};
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Action {
+    StartStop,
+    StartWith,
+    Ephemeral,
+    Import,
+    Shell,
+    Reboot,
+    Info,
+    Clone,
+    Rename,
+    Remove,
+    ReadOnly,
+    Limit,
+    PoolLimit,
+    CleanPool,
+    Console,
+    Copy,
+    Bind,
+    Rebind,
+    Autostart,
+    Freeze,
+    Kill,
+    Terminate,
+    Processes,
+    Sort,
+    Log,
+    Settings,
+    NextTab,
+    CloseTab,
+    Refresh,
+    Help,
+    Quit,
+    Up,
+    Down,
+}
+
+pub struct Binding {
+    pub key: Received,
+    // how the key is shown in the help, bindings without help are not shown
+    pub label: &'static str,
+    pub help: &'static str,
+    pub action: Action,
+}
+
+pub const BINDINGS: &[Binding] = &[
+    Binding { key: Received::Key(Key::Enter), label: "Enter", help: "Start/Stop", action: Action::StartStop },
+    Binding { key: Received::Char('S'), label: "S", help: "Start with", action: Action::StartWith },
+    Binding { key: Received::Char('E'), label: "E", help: "Ephemeral", action: Action::Ephemeral },
+    Binding { key: Received::Key(Key::Right), label: "Right", help: "Shell", action: Action::Shell },
+    Binding { key: Received::Char('r'), label: "r", help: "Reboot", action: Action::Reboot },
+    Binding { key: Received::Char('i'), label: "i", help: "Info", action: Action::Info },
+    Binding { key: Received::Char('c'), label: "c", help: "Clone", action: Action::Clone },
+    Binding { key: Received::Key(Key::F02), label: "F2", help: "Rename", action: Action::Rename },
+    Binding { key: Received::Key(Key::Del), label: "Del", help: "Remove", action: Action::Remove },
+    Binding { key: Received::Char('w'), label: "w", help: "ro/rw", action: Action::ReadOnly },
+    Binding { key: Received::Char('l'), label: "l", help: "Limit", action: Action::Limit },
+    Binding { key: Received::Char('L'), label: "L", help: "Pool limit", action: Action::PoolLimit },
+    Binding { key: Received::Char('X'), label: "X", help: "Clean pool", action: Action::CleanPool },
+    Binding { key: Received::Char('I'), label: "I", help: "Import", action: Action::Import },
+    Binding { key: Received::Char('t'), label: "t", help: "Terminal", action: Action::Console },
+    Binding { key: Received::Char('C'), label: "C", help: "Copy", action: Action::Copy },
+    Binding { key: Received::Char('b'), label: "b", help: "Bind", action: Action::Bind },
+    Binding { key: Received::Char('B'), label: "B", help: "Re-bind", action: Action::Rebind },
+    Binding { key: Received::Char('a'), label: "a", help: "Autostart", action: Action::Autostart },
+    Binding { key: Received::Char('p'), label: "p", help: "Pause", action: Action::Freeze },
+    Binding { key: Received::Char('k'), label: "k", help: "Kill", action: Action::Kill },
+    Binding { key: Received::Char('K'), label: "K", help: "Terminate", action: Action::Terminate },
+    Binding { key: Received::Char('P'), label: "P", help: "Processes", action: Action::Processes },
+    Binding { key: Received::Char('s'), label: "s", help: "Sort", action: Action::Sort },
+    Binding { key: Received::Char('j'), label: "j", help: "Journal", action: Action::Log },
+    Binding { key: Received::Char('e'), label: "e", help: "Settings", action: Action::Settings },
+    Binding { key: Received::Key(Key::F06), label: "F6", help: "Tabs", action: Action::NextTab },
+    Binding { key: Received::Key(Key::F08), label: "F8", help: "Close tab", action: Action::CloseTab },
+    Binding { key: Received::Key(Key::F05), label: "F5", help: "Refresh", action: Action::Refresh },
+    Binding { key: Received::Char('?'), label: "?", help: "help", action: Action::Help },
+    Binding { key: Received::Char('q'), label: "q", help: "quit", action: Action::Quit },
+    Binding { key: Received::Key(Key::Up), label: "Up", help: "", action: Action::Up },
+    Binding { key: Received::Key(Key::Down), label: "Down", help: "", action: Action::Down },
+    // scroll wheel
+    Binding { key: Received::Key(Key::Button4), label: "", help: "", action: Action::Up },
+    Binding { key: Received::Key(Key::Button5), label: "", help: "", action: Action::Down },
+];
+
+// what an input event means to mat
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Command {
+    Action(Action),
+    // left click at an absolute screen position
+    Click(Position),
+    Ignore,
+    Unbound(Received),
+}
+
+pub fn lookup(key: Received) -> Option<Action> {
+    BINDINGS.iter().find(|b| b.key == key).map(|b| b.action)
+}
+
+pub fn translate(input: &Input) -> Command {
+    if input.is_release() {
+        return Command::Ignore;
+    }
+    if let Some(action) = lookup(input.received) {
+        return Command::Action(action);
+    }
+    match input.received {
+        Received::NoInput => Command::Ignore,
+        Received::Key(Key::Resize) => Command::Ignore,
+        Received::Key(Key::Button1) => match input.cell {
+            Some(pos) => Command::Click(pos),
+            None => Command::Ignore,
+        },
+        Received::Key(k) if k.is_mouse() => Command::Ignore,
+        r => Command::Unbound(r),
+    }
+}
+
+// the bindings in the status line, the rest is in the help popup
+const SHORT_HELP: &[Action] = &[Action::StartStop, Action::Shell, Action::Info, Action::Log, Action::Settings, Action::Help, Action::Quit];
+
+pub fn help_line() -> String {
+    BINDINGS.iter().filter(|b| !b.help.is_empty() && SHORT_HELP.contains(&b.action))
+        .map(|b| format!("{}: {}", b.label, b.help)).collect::<Vec<_>>().join(", ")
+}
+
+// all documented bindings in two columns
+pub fn help_table() -> String {
+    let entries: Vec<String> = BINDINGS.iter().filter(|b| !b.help.is_empty())
+        .map(|b| format!("{:>5}  {:12}", b.label, b.help)).collect();
+    let rows = entries.len().div_ceil(2);
+    (0..rows).map(|r| {
+        let right = entries.get(r + rows).map_or("", |e| e.as_str());
+        format!("{}  {}", entries[r], right).trim_end().to_string()
+    }).collect::<Vec<_>>().join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notcurses::{KeyMod,InputType};
+
+    fn input(received: Received, itype: InputType) -> Input {
+        Input { received, keymod: KeyMod::None, itype, cell: None, offset: None }
+    }
+
+    #[test]
+    fn help_line_lists_documented_bindings() {
+        assert_eq!(help_line(), "Enter: Start/Stop, Right: Shell, i: Info, j: Journal, e: Settings, ?: help, q: quit");
+    }
+
+    #[test]
+    fn help_table_lists_every_binding_once() {
+        let table = help_table();
+        // fits a message popup on an 80 column terminal
+        assert!(table.lines().all(|l| l.chars().count() <= 72), "{}", table);
+        // down the left column, then the right one
+        let lines: Vec<&str> = table.lines().collect();
+        let cells: Vec<&str> = lines.iter().map(|l| l.get(..19).unwrap_or(l)).chain(lines.iter().filter_map(|l| l.get(21..)))
+            .map(|c| c.trim()).filter(|c| !c.is_empty()).collect();
+        let documented: Vec<String> = BINDINGS.iter().filter(|b| !b.help.is_empty()).map(|b| format!("{}  {}", b.label, b.help)).collect();
+        assert_eq!(cells, documented);
+    }
+
+    #[test]
+    fn release_is_ignored() {
+        assert_eq!(translate(&input(Received::Char('q'), InputType::Release)), Command::Ignore);
+        assert_eq!(translate(&input(Received::Char('q'), InputType::Press)), Command::Action(Action::Quit));
+    }
+
+    #[test]
+    fn unbound_keys_are_reported() {
+        assert_eq!(translate(&input(Received::Char('x'), InputType::Press)), Command::Unbound(Received::Char('x')));
+    }
+
+    #[test]
+    fn mouse() {
+        assert_eq!(translate(&input(Received::Key(Key::Button5), InputType::Press)), Command::Action(Action::Down));
+        assert_eq!(translate(&input(Received::Key(Key::Motion), InputType::Press)), Command::Ignore);
+        let mut click = input(Received::Key(Key::Button1), InputType::Press);
+        click.cell = Some(Position::new(3, 4));
+        assert_eq!(translate(&click), Command::Click(Position::new(3, 4)));
+    }
+}
@@ -0,0 +1,164 @@
+use std::time::Instant;
+
+use journal::{Entry,Journal};
+
+// one line of the log pane
+#[derive(Clone,Debug,PartialEq)]
+pub struct Line {
+    // µs since the epoch
+    pub realtime: u64,
+    pub priority: u8,
+    pub text: String,
+}
+
+// the journal of an image's service or of the machine itself
+#[derive(Debug)]
+pub struct LogView {
+    pub name: String,
+    // the container's own journal instead of the one of its service
+    pub machine: bool,
+    pub title: String,
+    journal: Journal,
+    pub lines: Vec<Line>,
+    // the most verbose priority shown, 7 for everything
+    pub priority: u8,
+    pub search: Option<String>,
+    // stay on the newest line
+    pub follow: bool,
+    // the selected line, the pane ends with it
+    pub current: usize,
+    pub loaded: Instant,
+}
+
+impl LogView {
+
+    pub fn new(name: &str, machine: bool, title: &str, journal: Journal, now: Instant) -> LogView {
+        let mut view = LogView {
+            name: name.to_string(),
+            machine,
+            title: title.to_string(),
+            journal,
+            lines: Vec::new(),
+            priority: 7,
+            search: None,
+            follow: true,
+            current: 0,
+            loaded: now,
+        };
+        view.reload(now);
+        view
+    }
+
+    // true if there are new lines
+    pub fn reload(&mut self, now: Instant) -> bool {
+        self.loaded = now;
+        if !self.journal.refresh() {
+            return false;
+        }
+        let before = self.lines.len();
+        self.filter();
+        self.lines.len() != before
+    }
+
+    fn wanted(&self, e: &Entry) -> bool {
+        e.priority() <= self.priority
+    }
+
+    fn filter(&mut self) {
+        // stay near the selected line when it's not shown anymore
+        let selected = self.lines.get(self.current).map(|l| l.realtime);
+        self.lines = self.journal.entries().filter(|e| self.wanted(e)).map(|e| Line {
+            realtime: e.realtime,
+            priority: e.priority(),
+            text: format!("{}: {}", e.identifier(), e.message()),
+        }).collect();
+        self.current = match selected {
+            Some(t) if !self.follow => self.lines.iter().position(|l| l.realtime >= t).unwrap_or(self.lines.len()),
+            _ => self.lines.len(),
+        };
+        self.current = self.current.min(self.lines.len().saturating_sub(1));
+    }
+
+    pub fn set_priority(&mut self, priority: u8) {
+        self.priority = priority;
+        self.filter();
+    }
+
+    pub fn toggle_follow(&mut self) {
+        self.follow = !self.follow;
+        if self.follow {
+            self.current = self.lines.len().saturating_sub(1);
+        }
+    }
+
+    // positive is towards the newer lines, leaves follow mode
+    pub fn scroll(&mut self, lines: isize) {
+        let last = self.lines.len().saturating_sub(1);
+        self.current = if lines < 0 { self.current.saturating_sub(lines.unsigned_abs()) } else { (self.current + lines as usize).min(last) };
+        self.follow = false;
+    }
+
+    // the previous line with the search text, starting over at the newest
+    pub fn find(&mut self) -> bool {
+        let search = match self.search {
+            Some(ref s) => s.to_lowercase(),
+            None => return false,
+        };
+        let n = self.lines.len();
+        for i in 1..=n {
+            let idx = (self.current + n - i) % n;
+            if self.lines[idx].text.to_lowercase().contains(&search) {
+                self.current = idx;
+                self.follow = false;
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use journal::tests::write_journal;
+    use testdir::TempDir;
+
+    fn texts(view: &LogView) -> Vec<&str> {
+        view.lines.iter().map(|l| l.text.as_str()).collect()
+    }
+
+    #[test]
+    fn filters_and_follows() {
+        let dir = TempDir::new("logview");
+        let path = dir.join("system.journal");
+        write_journal(&path, true, &[
+            (1, &["MESSAGE=Starting leap", "UNIT=systemd-nspawn@leap.service", "SYSLOG_IDENTIFIER=systemd", "_PID=1"]),
+            (2, &["MESSAGE=booting", "_SYSTEMD_UNIT=systemd-nspawn@leap.service", "SYSLOG_IDENTIFIER=systemd-nspawn", "_PID=7"]),
+            (3, &["MESSAGE=other", "_SYSTEMD_UNIT=sshd.service", "SYSLOG_IDENTIFIER=sshd"]),
+            (4, &["MESSAGE=failed", "PRIORITY=3", "_SYSTEMD_UNIT=systemd-nspawn@leap.service", "SYSLOG_IDENTIFIER=systemd-nspawn"]),
+        ]);
+        let unit = "systemd-nspawn@leap.service".to_string();
+        let matches = vec![("_SYSTEMD_UNIT".to_string(), unit.clone()), ("UNIT".to_string(), unit)];
+        let now = Instant::now();
+        let mut view = LogView::new("leap", false, "leap", Journal::new(vec![dir.clone()], matches), now);
+        assert_eq!(texts(&view), vec!["systemd[1]: Starting leap", "systemd-nspawn[7]: booting", "systemd-nspawn: failed"]);
+        assert_eq!(view.current, 2);
+
+        view.set_priority(3);
+        assert_eq!(texts(&view), vec!["systemd-nspawn: failed"]);
+        view.set_priority(7);
+
+        view.scroll(-1);
+        assert!(!view.follow);
+        assert_eq!(view.current, 1);
+        view.search = Some("START".to_string());
+        assert!(view.find());
+        assert_eq!(view.current, 0);
+        view.search = Some("nothing".to_string());
+        assert!(!view.find());
+
+        view.toggle_follow();
+        assert_eq!(view.current, 2);
+        assert!(!view.reload(now));
+    }
+}
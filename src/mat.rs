@@ -1,16 +1,43 @@
 extern crate dbus;
 extern crate notcurses;
+extern crate libc;
 
-use std::process::Command;
 use dbus::blocking::Connection;
-use std::time::Duration;
-use std::collections::HashMap;
-use notcurses::{Notcurses,Received,Key,Style,Plane,Channel,Channels,Alpha,Position,Size};
+use dbus::channel::{BusType,Channel as DbusChannel};
+use std::time::{Duration,Instant};
+use std::sync::mpsc;
+use std::os::unix::io::{AsRawFd,RawFd};
+use notcurses::{Notcurses,Input,Received,Key,MiceEvents,Style,Plane,Channel,Channels,Alpha,Position,Size};
 
 mod machined;
-use machined::manager::OrgFreedesktopMachine1Manager;
 mod systemd;
-use systemd::manager::OrgFreedesktopSystemd1Manager;
+mod import1;
+mod backend;
+use backend::{DbusBackend,BusEvent,ImageDetails,MachineBackend};
+mod config;
+use config::Config;
+mod pty;
+mod vt;
+mod console;
+mod complete;
+mod procs;
+use procs::ProcessView;
+mod journal;
+mod logs;
+use logs::LogView;
+mod nspawn;
+mod app;
+use app::{App,Outcome,Pending,Popup};
+mod error;
+mod keymap;
+mod form;
+use form::{Form,Value};
+mod format;
+use format::{format_size,format_time};
+#[cfg(test)]
+mod testdir;
+
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
 
 // https://en.opensuse.org/Help:Colors
 // primary
@@ -25,93 +52,108 @@ const OPENSUSE_BLUE      :(u32, u32, u32, u32, u32) = (0x21a4df, 0x38ade2, 0x59b
 const BORDERS_ROUND: (&str, &str, &str, &str, &str, &str, &str, &str) = ("╭","╮","╰","╯","─","│","├","┤");
 const BORDERS_LIGHT: (&str, &str, &str, &str, &str, &str, &str, &str) = ("┌","┐","└","┘","─","│","├","┤");
 
-const SIZE_UNITS: [&str; 5] = ["", "k", "M", "G", "T"];
 
-
-#[allow(dead_code)]
-struct Machine {
-    name: String,
-    class: String,
-    id: String,
-    path: dbus::Path<'static>,
-}
-
-#[allow(dead_code)]
-struct Image {
-    name: String,
-    t: String,
-    ro: bool,
-    t_created: u64,
-    t_modified: u64,
-    size: u64,
-    path: dbus::Path<'static>,
-    machine: Option<Machine>,
+enum Event {
+    Input(Input),
+    Bus(BusEvent),
+    // a console pty, by index
+    Pty(usize),
+    Tick,
 }
 
-fn update_images(images: &mut Vec<Image>, machined: &dbus::blocking::Proxy<'_, &dbus::blocking::Connection>) -> Result<(), Box<dyn std::error::Error>> {
-
-    let mut running = HashMap::new();
-
-    if let Ok(l) = machined.list_machines() {
-        for i in l {
-            let m = Machine { name: i.0, class: i.1, id: i.2, path: i.3 };
-            running.insert(m.name.clone(), m);
+// wait for either terminal input, a D-Bus signal or console output, or until the timeout expired
+fn next_event(nc: &Notcurses, conn: &Connection, bus: &mpsc::Receiver<BusEvent>, ptys: &[RawFd], timeout: Option<Duration>) -> Result<Event, Box<dyn std::error::Error>> {
+    let ncfd = nc.with_nc_mut(|nc| nc.inputready_fd())?;
+    loop {
+        // messages may have been queued while waiting for a method reply,
+        // so drain those before going to sleep
+        while conn.process(Duration::ZERO)? {}
+        if let Ok(ev) = bus.try_recv() {
+            return Ok(Event::Bus(ev));
         }
-    }
-    if let Ok(l) = machined.list_images() {
-        images.clear();
-        for i in l {
-            if i.0.starts_with('.') {
-                continue;
-            }
-            let on = running.contains_key(&i.0);
-            let img = Image { name: i.0.clone(), t: i.1, ro: i.2, t_created: i.3, t_modified: i.4, size: i.5, path: i.6, machine: if on {running.remove(&i.0) } else {Option::None} };
-            images.push(img);
+        let input = nc.poll_event()?;
+        if input.received() {
+            return Ok(Event::Input(input));
+        }
+        let mut fds = vec![
+            libc::pollfd { fd: ncfd, events: libc::POLLIN, revents: 0 },
+            libc::pollfd { fd: conn.channel().watch().fd, events: libc::POLLIN, revents: 0 },
+        ];
+        fds.extend(ptys.iter().map(|&fd| libc::pollfd { fd, events: libc::POLLIN, revents: 0 }));
+        let ms = timeout.map_or(-1, |t| t.as_millis() as libc::c_int);
+        match unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, ms) } {
+            0 => return Ok(Event::Tick),
+            n if n < 0 => {
+                let err = std::io::Error::last_os_error();
+                if err.kind() != std::io::ErrorKind::Interrupted {
+                    return Err(err.into());
+                }
+            },
+            _ => {
+                if let Some(i) = fds[2..].iter().position(|p| p.revents != 0) {
+                    return Ok(Event::Pty(i));
+                }
+            },
         }
-        images.sort_by(|a,b| a.name.cmp(&b.name));
     }
-    Ok(())
 }
 
-fn draw_images(plane: &mut Plane, images: &Vec<Image>, current: usize) -> Result<(), Box<dyn std::error::Error>> {
+fn draw_images(plane: &mut Plane, app: &App) -> Result<(), Box<dyn std::error::Error>> {
     plane.into_ref_mut().erase();
     plane.cursor_home();
 
+    // XXX: calculate available space
+    let maxlen: usize = (plane.size().0 as usize).saturating_sub(53).max(8);
+    plane.on_styles(Style::Bold);
+    plane.putstr_at((0, 0), &format!("    {:maxlen$} {:>9} {:4} {:2} {:>5} {:>6} {:>5} {:>5} {:>5}", "Name", "", "", "", "Size", "CPU", "Mem", "Tasks", "IO"))?;
+    plane.off_styles(Style::Bold);
+
+    let current = app.current;
     let mut idx: usize = 0;
-    for img in images {
+    for img in &app.images {
             let bg = plane.bg();
             if idx == current {
                 plane.set_bg(OPENSUSE_DARK_BLUE.2);
             }
-            plane.cursor_move_to((0, idx));
+            plane.cursor_move_to((0, idx + 1))?;
             if img.machine.is_some() {
                 let fg = plane.fg();
                 plane.set_fg(0xFF0000);
                 plane.putstr("❤️ ")?;
+                if img.frozen {
+                    plane.set_fg(0x00BFFF);
+                    plane.putstr("❄ ")?;
+                } else {
+                    plane.putstr("  ")?;
+                }
                 plane.set_fg(fg);
                 plane.on_styles(Style::Bold);
             } else {
-                plane.putstr("  ")?;
-            }
-            let mut ss = "".to_string();
-            if img.size > 1<<(10*(SIZE_UNITS.len())) {
-                ss = "-".to_string();
-            } else {
-                for i in (0..SIZE_UNITS.len()).rev() {
-                    if img.size > 1<<(10*i) {
-                        ss = format!("{}{}", img.size>>(10*i), SIZE_UNITS[i]);
-                        break;
-                    }
-                }
+                plane.putstr("    ")?;
             }
+            // ephemeral runs hang below their image, the size of the snapshot is unknown
+            let (indent, ss) = match img.source {
+                Some(_) => ("└ ", "-".to_string()),
+                None => ("", format_size(img.size)),
+            };
+            let room = maxlen - indent.chars().count();
             let mut name = img.name.clone();
-            // XXX: calculate available space
-            let maxlen: usize = plane.size().0 as usize - 11;
-            if name.len() > maxlen {
-                name.truncate(maxlen - 2);
+            if name.len() > room {
+                name.truncate(room - 2);
                 name.push_str("..");
             }
-            let s = format!("{:maxlen$} {} {:>5}", name, if img.ro { "ro" } else { "rw" }, ss);
+            let name = format!("{}{}", indent, name);
+            let state = match app.pending.get(&img.name) {
+                Some(&Pending::Starting(_)) => "starting…",
+                Some(&Pending::Stopping(_)) => "stopping…",
+                Some(&Pending::Killing(_)) => "killing…",
+                None => "",
+            };
+            let usage = img.usage.unwrap_or_default();
+            let cpu = img.cpu_percent.map_or(String::new(), |p| format!("{:.1}%", p));
+            let tasks = usage.tasks.map_or(String::new(), |t| t.to_string());
+            let s = format!("{:maxlen$} {:>9} {:4} {} {:>5} {:>6} {:>5} {:>5} {:>5}", name, state, if img.autostart { "boot" } else { "" }, if img.ro { "ro" } else { "rw" }, ss,
+                            cpu, usage.memory.map_or(String::new(), format_size), tasks, usage.io.map_or(String::new(), format_size));
             plane.putstr(&s)?;
             if img.machine.is_some() {
                 plane.off_styles(Style::Bold);
@@ -124,6 +166,231 @@ fn draw_images(plane: &mut Plane, images: &Vec<Image>, current: usize) -> Result
     Ok(())
 }
 
+fn draw_details(plane: &mut Plane, app: &App) -> Result<(), Box<dyn std::error::Error>> {
+    plane.into_ref_mut().erase();
+
+    let img = match app.selected() {
+        Some(img) => img,
+        None => return Ok(()),
+    };
+    let mut lines: Vec<(&str, String)> = Vec::new();
+    match app.selected_details() {
+        Some(&Ok(ref d)) => {
+            let os = |key: &str| d.os_release.get(key).cloned().unwrap_or_else(|| "-".to_string());
+            lines.push(("OS", os("PRETTY_NAME")));
+            lines.push(("Version", os("VERSION_ID")));
+            let mut hostname = d.hostname.clone().unwrap_or_else(|| "-".to_string());
+            if let Some(pretty) = d.machine_info.get("PRETTY_HOSTNAME") {
+                hostname = format!("{} ({})", hostname, pretty);
+            }
+            lines.push(("Hostname", hostname));
+            lines.push(("Machine ID", d.machine_id.clone().unwrap_or_else(|| "-".to_string())));
+        },
+        Some(&Err(ref e)) => {
+            lines.push(("Error", e.to_string().lines().next().unwrap_or("").to_string()));
+        },
+        None => {},
+    }
+    lines.push(("Type", img.t.clone()));
+    lines.push(("Created", format_time(img.t_created)));
+    lines.push(("Modified", format_time(img.t_modified)));
+    let mut usage = format_size(img.size);
+    if let Some(&Ok(ImageDetails { limit: Some(limit), .. })) = app.selected_details() {
+        usage = format!("{} (limit {})", usage, format_size(limit));
+    }
+    lines.push(("Disk usage", usage));
+
+    let width = plane.size().0 as usize;
+    for (i, &(key, ref value)) in lines.iter().enumerate() {
+        let s: String = format!("{:>10}: {}", key, value).chars().take(width).collect();
+        plane.putstr_at((0, i as u32), &s)?;
+    }
+    Ok(())
+}
+
+fn draw_console(plane: &mut Plane, screen: &vt::Screen) -> Result<(), Box<dyn std::error::Error>> {
+    plane.into_ref_mut().erase();
+    let (fg, bg) = (plane.fg(), plane.bg());
+    for (y, row) in screen.rows.iter().enumerate() {
+        let mut x = 0;
+        // one putstr per run of cells that look the same
+        while x < row.len() {
+            let mut attr = row[x].attr;
+            let cursor = screen.cursor_visible && screen.cursor == (x, y);
+            if cursor {
+                attr.reverse = !attr.reverse;
+            }
+            let mut end = x + 1;
+            while !cursor && end < row.len() && row[end].attr == row[x].attr && !(screen.cursor_visible && screen.cursor == (end, y)) {
+                end += 1;
+            }
+            let text: String = row[x..end].iter().map(|c| c.ch).collect();
+            let (mut f, mut b): (Channel, Channel) = (attr.fg.map_or(fg, Channel::from), attr.bg.map_or(bg, Channel::from));
+            if attr.reverse {
+                std::mem::swap(&mut f, &mut b);
+            }
+            plane.set_fg(f);
+            plane.set_bg(b);
+            if attr.bold {
+                plane.on_styles(Style::Bold);
+            }
+            plane.putstr_at((x as u32, y as u32), &text)?;
+            if attr.bold {
+                plane.off_styles(Style::Bold);
+            }
+            x = end;
+        }
+    }
+    plane.set_fg(fg);
+    plane.set_bg(bg);
+    Ok(())
+}
+
+fn popup_dialog_for(plane: &mut Plane, popup: &Popup) -> Result<Dialog, Box<dyn std::error::Error>> {
+    match *popup {
+        Popup::MachineInfo(ref name, Ok(ref m)) => {
+            let mut text = String::new();
+            text += &format!("{:>10}: {}\n", "Class", m.class);
+            text += &format!("{:>10}: {}\n", "Machine ID", m.id);
+            text += &format!("{:>10}: {}\n", "Leader", m.leader);
+            text += &format!("{:>10}: {}\n", "Service", m.service);
+            text += &format!("{:>10}: {}\n", "Unit", m.unit);
+            if let Some(os) = m.os_release.get("PRETTY_NAME") {
+                text += &format!("{:>10}: {}\n", "OS", os);
+            }
+            if m.addresses.is_empty() {
+                text += &format!("{:>10}: -\n", "Addresses");
+            }
+            for (i, a) in m.addresses.iter().enumerate() {
+                text += &format!("{:>10}: {}\n", if i == 0 { "Addresses" } else { "" }, a);
+            }
+            Dialog::new_message(plane, name, &text)
+        },
+        Popup::MachineInfo(ref name, Err(ref e)) => Dialog::new_message(plane, name, &e.to_string()),
+        Popup::Form(_, ref form) => Dialog::new_form(plane, form),
+        Popup::Message(ref title, ref text) => Dialog::new_message(plane, title, text),
+        Popup::Confirm(_, ref question) => {
+            let mut lines = wrap(question, plane.size().0 as usize - 8);
+            lines.push(String::new());
+            lines.push("Enter: yes, Esc: no".to_string());
+            Dialog::new_lines(plane, "Confirm", &lines, None)
+        },
+    }
+}
+
+// indented by cgroup, scrolled so the selected row is visible
+fn draw_processes(plane: &mut Plane, view: &ProcessView) -> Result<(), Box<dyn std::error::Error>> {
+    plane.into_ref_mut().erase();
+    let (width, height) = (plane.size().0 as usize, plane.size().1 as usize);
+    let first = (view.current + 1).saturating_sub(height);
+    let bg = plane.bg();
+    for (i, row) in view.rows.iter().enumerate().skip(first).take(height) {
+        let s: String = format!("{}{}", "  ".repeat(row.depth), row.text).chars().take(width).collect();
+        if i == view.current {
+            plane.set_bg(OPENSUSE_DARK_BLUE.2);
+        }
+        if row.pid.is_none() {
+            plane.on_styles(Style::Bold);
+        }
+        plane.putstr_at((0, (i - first) as u32), &s)?;
+        plane.off_styles(Style::Bold);
+        plane.set_bg(bg);
+    }
+    Ok(())
+}
+
+// ends with the selected line, errors in red and warnings in yellow
+fn draw_log(plane: &mut Plane, view: &LogView) -> Result<(), Box<dyn std::error::Error>> {
+    plane.into_ref_mut().erase();
+    let (width, height) = (plane.size().0 as usize, plane.size().1 as usize);
+    let first = (view.current + 1).saturating_sub(height);
+    let (fg, bg) = (plane.fg(), plane.bg());
+    let search = view.search.as_ref().map(|s| s.to_lowercase());
+    for (i, line) in view.lines.iter().enumerate().skip(first).take(height) {
+        let s: String = format!("{} {}", format_time(line.realtime), line.text).chars().take(width).collect();
+        if i == view.current {
+            plane.set_bg(OPENSUSE_DARK_BLUE.2);
+        }
+        match line.priority {
+            0..=3 => { plane.set_fg(0xFF5555); },
+            4 => { plane.set_fg(0xFFD75F); },
+            _ => {},
+        }
+        if line.priority <= 5 || search.as_ref().is_some_and(|s| line.text.to_lowercase().contains(s)) {
+            plane.on_styles(Style::Bold);
+        }
+        plane.putstr_at((0, (i - first) as u32), &s)?;
+        plane.off_styles(Style::Bold);
+        plane.set_fg(fg);
+        plane.set_bg(bg);
+    }
+    Ok(())
+}
+
+// pool location and how full it is, on the top line
+fn draw_header(plane: &mut Plane, app: &App) -> Result<(), Box<dyn std::error::Error>> {
+    let width = plane.size().0 as usize - 2;
+    let mut header = String::new();
+    if let Some(ref pool) = app.pool {
+        header = format!("Pool: {}", pool.path);
+        match (pool.usage, pool.limit) {
+            (Some(usage), Some(limit)) if limit > 0 => {
+                const GAUGE: usize = 20;
+                let filled = ((usage as f64 / limit as f64).min(1.0) * GAUGE as f64).round() as usize;
+                header += &format!("  {} of {} [{}{}] {}%", format_size(usage), format_size(limit),
+                    "█".repeat(filled), "░".repeat(GAUGE - filled), usage * 100 / limit);
+            },
+            (Some(usage), _) => header += &format!("  {} used, no limit", format_size(usage)),
+            (None, _) => {},
+        }
+    }
+    let header: String = header.chars().take(width).collect();
+    plane.putstr_at((1,0), &format!("{:width$}", header))?;
+    Ok(())
+}
+
+fn draw_status(plane: &mut Plane, app: &App) -> Result<(), Box<dyn std::error::Error>> {
+    let size = plane.size();
+    let width = size.0 as usize - 2;
+    let msg = match (app.status.as_ref(), app.tasks.first()) {
+        (Some(s), _) => s.clone(),
+        (None, Some(task)) => format!("{} {}…", SPINNER[app.spinner % SPINNER.len()], task.describe()),
+        (None, None) => app.transfers.iter().map(|t| t.describe()).collect::<Vec<_>>().join(", "),
+    };
+    let msg: String = msg.chars().take(width).collect();
+    plane.putstr_at((1,size.1-2), &format!("{:width$}", msg))?;
+    Ok(())
+}
+
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for para in text.lines() {
+        // keep the layout of lines that fit
+        if para.chars().count() <= width {
+            lines.push(para.to_string());
+            continue;
+        }
+        let mut line = String::new();
+        for word in para.split_whitespace() {
+            if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+                lines.push(line);
+                line = String::new();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+        // hard break whatever is still too long, e.g. URLs
+        let mut chars: Vec<char> = line.chars().collect();
+        while chars.len() > width {
+            lines.push(chars.drain(..width).collect());
+        }
+        lines.push(chars.into_iter().collect());
+    }
+    lines
+}
+
 struct Dialog {
     title: String,
     pos: Position,
@@ -145,7 +412,7 @@ impl Dialog {
 
     fn new_sized_at(parent: &mut Plane, size: Size, pos: Position, shadow: bool) -> Result<Dialog, Box<dyn std::error::Error>> {
         let d = parent.new_child_sized_at(size, pos)?;
-        let mut content = parent.new_child_sized_at((size.0-(if shadow {4} else {3}), size.1-3), (pos.0+1,pos.1+1))?;
+        let mut content = parent.new_child_sized_at((size.0.saturating_sub(if shadow {4} else {3}), size.1.saturating_sub(3)), (pos.0+1,pos.1+1))?;
         content.set_base(" ", Style::None, Channels::from_rgb(OPENSUSE_CYAN.0, OPENSUSE_DARK_BLUE.1))?;
         content.set_scrolling(true);
 
@@ -174,6 +441,69 @@ impl Dialog {
         Ok(di)
     }
 
+    // centered dialog with a title and word wrapped text
+    fn new_message(parent: &mut Plane, title: &str, text: &str) -> Result<Dialog, Box<dyn std::error::Error>> {
+        let lines = wrap(text, parent.size().0.saturating_sub(8) as usize);
+        Dialog::new_lines(parent, title, &lines, None)
+    }
+
+    // input fields one per line, the focused one highlighted
+    fn new_form(parent: &mut Plane, form: &Form) -> Result<Dialog, Box<dyn std::error::Error>> {
+        let labelw = form.fields.iter().map(|f| f.label.chars().count()).max().unwrap_or(0);
+        let mut lines: Vec<String> = form.fields.iter().enumerate().map(|(i, f)| {
+            let value = match f.value {
+                Value::Text(ref s) if i == form.focus => format!("{}_", s),
+                Value::Text(ref s) => s.clone(),
+                Value::Toggle(b) => (if b { "[x]" } else { "[ ]" }).to_string(),
+                Value::Choice(ref o, idx) => format!("< {} >", o[idx]),
+            };
+            format!("{:>labelw$}: {:30}", f.label, value)
+        }).collect();
+        lines.push(String::new());
+        if let Some(ref msg) = form.message {
+            lines.extend(wrap(msg, parent.size().0.saturating_sub(8) as usize));
+        }
+        if form.fields.get(form.focus).is_some_and(|f| f.complete) {
+            lines.push("Tab: complete, Down: next field, Enter: OK, Esc: cancel".to_string());
+        } else {
+            lines.push("Tab: next field, Space: toggle, Enter: OK, Esc: cancel".to_string());
+        }
+        Dialog::new_lines(parent, &form.title, &lines, Some(form.focus))
+    }
+
+    fn new_lines(parent: &mut Plane, title: &str, lines: &[String], highlight: Option<usize>) -> Result<Dialog, Box<dyn std::error::Error>> {
+        let size = parent.size();
+        let w = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0).max(title.chars().count() + 4) as u32;
+        let w = w.min(size.0 - 8);
+        let h = lines.len() as u32;
+        let x = size.0/2-w/2;
+        let y = size.1/2-h/2;
+        let d = parent.new_child_sized_at((w + 4, h + 3), (x-1, y-1))?;
+        let mut content = parent.new_child_sized_at((w, h),(x, y))?;
+        content.set_base(" ", Style::None, Channels::from_rgb(OPENSUSE_CYAN.0, OPENSUSE_DARK_BLUE.1))?;
+        for (i, l) in lines.iter().enumerate() {
+            let l: String = l.chars().take(w as usize).collect();
+            if highlight == Some(i) {
+                let bg = content.bg();
+                content.set_bg(OPENSUSE_DARK_BLUE.2);
+                content.putstr_at((0, i as u32), &l)?;
+                content.set_bg(bg);
+            } else {
+                content.putstr_at((0, i as u32), &l)?;
+            }
+        }
+
+        let mut di = Self { title: title.to_string(), pos: d.position(), size: d.size(), has_shadow: true, d, content};
+        di.draw_borders()?;
+
+        Ok(di)
+    }
+
+    fn set_title(&mut self, title: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.title = title.to_string();
+        self.draw_borders()
+    }
+
     fn draw_borders(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         let mut d = &mut self.d;
         let size = d.size();
@@ -224,6 +554,10 @@ impl Dialog {
         d.putstr(b.3)?;
         d.set_fg(fg);
 
+        if !self.title.is_empty() {
+            d.putstr_at((2,0), &format!(" {} ", self.title))?;
+        }
+
         if self.has_shadow {
             d.set_channels(Channels::from_rgb_alpha(0, Alpha::Transparent, 0, Alpha::Transparent));
             d.putstr_at((x-2,0), "  ")?;
@@ -250,114 +584,209 @@ impl Dialog {
     }
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-
-    let mut cmd = Command::new("machinectl");
-
-    {
-        let mut nc = Notcurses::new()?;
+// where the panes go for the current terminal size
+struct Layout {
+    list: Dialog,
+    details: Option<Dialog>,
+    // where consoles, processes and logs are shown, if there is room
+    console: Option<(Position, Size)>,
+}
 
-        let mut plane = Plane::new(&mut nc)?;
-        plane.set_base(" ", Style::None, Channels::from_rgb(OPENSUSE_CYAN.0, OPENSUSE_DARK_BLUE.0))?;
+impl Layout {
 
+    fn new(plane: &mut Plane, app: &mut App) -> Result<Layout, Box<dyn std::error::Error>> {
         let size = plane.size();
+        let help: String = keymap::help_line().chars().take(size.0.saturating_sub(2) as usize).collect();
+        plane.putstr_at((1,size.1-1), &help)?;
+
+        // details pane below the list if the terminal is large enough
+        let details_h = if size.1 >= 24 { 11 } else { 0 };
+        let list = Dialog::new_sized_at(plane, (size.0.saturating_sub(2), size.1.saturating_sub(3 + details_h)).into(), (1,1).into(), true)?;
+        let mut details = None;
+        if details_h > 0 {
+            let mut d = Dialog::new_sized_at(plane, (size.0.saturating_sub(2), details_h).into(), (1, size.1.saturating_sub(2 + details_h)).into(), true)?;
+            d.set_title("Details")?;
+            details = Some(d);
+        }
 
-        let conn = Connection::new_system()?;
+        // consoles cover the lower part, the top third of the list stays visible.
+        // Without room for a line of text there is no pane
+        let console_pos: Position = (1, size.1/3).into();
+        let console_size: Size = (size.0.saturating_sub(2), size.1.saturating_sub(2 + size.1/3)).into();
+        let console = if console_size.0 > 4 && console_size.1 > 3 {
+            app.set_console_size(((console_size.0-4) as usize, (console_size.1-3) as usize));
+            Some((console_pos, console_size))
+        } else {
+            None
+        };
+
+        Ok(Layout { list, details, console })
+    }
+}
 
-        let machined = conn.with_proxy("org.freedesktop.machine1", "/org/freedesktop/machine1", Duration::from_millis(5000));
+// runs the UI until it has to step aside, the terminal is restored on return
+fn ui(app: &mut App, backend: &DbusBackend, bus_rx: &mpsc::Receiver<BusEvent>, first: bool) -> Result<Outcome, Box<dyn std::error::Error>> {
+    let mut nc = Notcurses::new()?;
 
-        let mut images: Vec<Image> = Vec::new();
-        update_images(&mut images, &machined)?;
+    let mut plane = Plane::new(&mut nc)?;
+    plane.set_base(" ", Style::None, Channels::from_rgb(OPENSUSE_CYAN.0, OPENSUSE_DARK_BLUE.0))?;
+
+    if first {
+        if let Err(e) = app.update(backend) {
+            let txt = Dialog::new_message(&mut plane, "Listing images failed", &e.to_string())?;
+            plane.render()?;
+            nc.get_event()?;
+            drop(txt);
+            return Err(e.into());
+        }
 
-        if images.len() == 0 {
+        if app.images.len() == 0 {
             let mut txt = Dialog::new_centered_text(&mut plane, "No images found", true)?;
             plane.render()?;
-            nc.get_event();
+            nc.get_event()?;
             return Err("No images found, read https://en.opensuse.org/Systemd-machined".into());
         }
+    }
 
-        plane.putstr_at((1,size.1-1), "Enter: Start/Stop, Right: Shell, F5: Refresh, q: quit")?;
-
-        let mut di = Dialog::new_sized_at(&mut plane, (size.0-2, size.1-3).into(), (1,1).into(), true)?;
-        let mut current: usize = 0;
-        draw_images(&mut di.content, &images, current);
-
-        let systemd = conn.with_proxy("org.freedesktop.systemd1", "/org/freedesktop/systemd1", Duration::from_millis(5000));
-
+    nc.mice_enable(MiceEvents::Button)?;
+    let mut layout = Layout::new(&mut plane, app)?;
+
+    // also brings back popups that were open before a shell session
+    let mut redraw = true;
+    let mut console_dialog: Option<Dialog> = None;
+    let mut popup_dialog: Option<Dialog> = None;
+    let mut error_dialog: Option<Dialog> = None;
+    loop {
+        if redraw {
+            layout.list.set_title(&format!("Images by {}", app.sort.label()))?;
+            draw_images(&mut layout.list.content, app)?;
+            if let Some(ref mut d) = layout.details {
+                app.load_details(backend);
+                draw_details(&mut d.content, app)?;
+            }
+            // recreate console, popup and error dialog so they stay on top
+            drop(popup_dialog.take());
+            error_dialog = None;
+            drop(console_dialog.take());
+            let console = layout.console;
+            if let (Some(idx), Some((pos, size))) = (app.active, console) {
+                let mut d = Dialog::new_sized_at(&mut plane, size, pos, true)?;
+                d.content.set_scrolling(false);
+                let tabs: Vec<String> = app.consoles.iter().enumerate()
+                    .map(|(i, c)| if i == idx { format!("[{} {}]", c.name, c.kind) } else { c.name.clone() }).collect();
+                d.set_title(&tabs.join(" "))?;
+                draw_console(&mut d.content, &app.consoles[idx].screen)?;
+                console_dialog = Some(d);
+            } else if let (Some(ref view), Some((pos, size))) = (&app.processes, console) {
+                // shares the place of the consoles
+                let mut d = Dialog::new_sized_at(&mut plane, size, pos, true)?;
+                d.content.set_scrolling(false);
+                d.set_title(&format!("Processes of {}", view.name))?;
+                draw_processes(&mut d.content, view)?;
+                console_dialog = Some(d);
+            } else if let (Some(ref view), Some((pos, size))) = (&app.log, console) {
+                let mut d = Dialog::new_sized_at(&mut plane, size, pos, true)?;
+                d.content.set_scrolling(false);
+                d.set_title(&format!("{} (priority {}{}) f: follow, 0-7: priority, /: search, n: next, m: {}",
+                    view.title, view.priority, if view.follow { ", following" } else { "" }, if view.machine { "service" } else { "machine" }))?;
+                draw_log(&mut d.content, view)?;
+                console_dialog = Some(d);
+            }
+            if let Some(ref p) = app.popup {
+                popup_dialog = Some(popup_dialog_for(&mut plane, p)?);
+            }
+        }
+        draw_header(&mut plane, app)?;
+        draw_status(&mut plane, app)?;
+        match app.error {
+            Some((ref what, ref e)) if error_dialog.is_none() => {
+                error_dialog = Some(Dialog::new_message(&mut plane, what, &e.to_string())?);
+            },
+            None => error_dialog = None,
+            _ => {},
+        }
         plane.render()?;
 
-        while let Ok(e) = nc.get_event() {
-            let mut update = false;
-            let mut redraw = false;
-            match e.received {
-                Received::Key(Key::Right) => {
-                    if images.len() > 0 && images[current].machine.is_some() {
-                        cmd.arg("shell").arg(images[current].name.clone());
-                        break;
-                    }
-                }
-                Received::Char('r') => {
-                    if images.len() > 0 && images[current].machine.is_some() {
-                        // reboot
-                        machined.kill_machine(&images[current].name, "leader", 2 /* SIGINT */);
-                    }
-                }
-                Received::Key(Key::Enter) => {
-                    if images.len() > 0 {
-                        if images[current].machine.is_some() {
-                            // poweroff
-                            machined.kill_machine(&images[current].name, "leader", 38 /* SIGRTMIN+4 */);
-                            let mut txt = Dialog::new_centered_text(&mut plane, "powering off", true)?;
-                            plane.render()?;
-                            // FIXME
-                            notcurses::sleep![2,0];
-                        } else {
-                            let name = format!("systemd-nspawn@{}.service", images[current].name);
-                            systemd.start_unit(&name, "fail");
-                            let mut txt = Dialog::new_centered_text(&mut plane, "starting", true)?;
-                            plane.render()?;
-                            // FIXME
-                            notcurses::sleep![2,0];
-                        }
-                        update = true;
-                    }
-                },
-                Received::Key(Key::Resize) => {},
-                //            Received::Key(notcurses::Received::Esc) => break,
-                Received::Key(Key::F05) => {
-                    current = 0;
-                    redraw = true;
-                },
-                Received::Char('q') => break,
-                Received::Key(Key::Up) => {
-                    if current > 0 {
-                        current -= 1;
-                    }
-                    redraw = true;
-                },
-                Received::Key(Key::Down) => {
-                    if current + 1 < images.len() {
-                        current += 1;
-                    }
-                    redraw = true;
-                },
-                _ => {
-                    return Err(format!("Invalid event {}", e).into());
+        let timeout = if !app.tasks.is_empty() {
+            Some(Duration::from_millis(100))
+        } else if !app.pending.is_empty() || !app.transfers.is_empty() || app.processes.is_some() || app.log.is_some() || app.images.iter().any(|i| i.machine.is_some()) {
+            Some(Duration::from_secs(1))
+        } else {
+            None
+        };
+        let ptys: Vec<RawFd> = app.consoles.iter().filter(|c| !c.exited).map(|c| c.pty.as_raw_fd()).collect();
+        let outcome = match next_event(&nc, backend.connection(), bus_rx, &ptys, timeout)? {
+            Event::Bus(ev) => app.handle_bus(ev),
+            Event::Pty(i) => {
+                // index among the live ones
+                let idx = app.consoles.iter().enumerate().filter(|&(_, c)| !c.exited).nth(i).map_or(0, |(idx, _)| idx);
+                app.console_output(idx)
+            },
+            Event::Tick => app.tick(backend, Instant::now()),
+            Event::Input(ref e) if e.received == Received::Key(Key::Resize) => {
+                // everything is laid out again at the new size
+                drop(popup_dialog.take());
+                error_dialog = None;
+                drop(console_dialog.take());
+                let (rows, cols) = nc.refresh()?;
+                plane.resize_simple((cols, rows))?;
+                plane.into_ref_mut().erase();
+                layout = Layout::new(&mut plane, app)?;
+                Outcome::Redraw
+            },
+            Event::Input(e) => match keymap::translate(&e) {
+                keymap::Command::Click(pos) if app.error.is_none() && app.active.is_none() => {
+                    // the first row holds the column names
+                    let row = pos.1 - layout.list.content.root_position().1 - 1;
+                    if row >= 0 { app.select(row as usize) } else { Outcome::Nothing }
                 },
+                _ => app.handle_input(backend, &e),
+            },
+        };
+        if app.config_changed {
+            app.config_changed = false;
+            if let Err(e) = app.config.save() {
+                app.status = Some(format!("Saving settings failed: {}", e));
             }
-            if update {
-                update_images(&mut images, &machined)?;
+        }
+        redraw = false;
+        match outcome {
+            Outcome::Nothing => {},
+            Outcome::Redraw => redraw = true,
+            Outcome::Update => {
+                app.refresh(backend);
                 redraw = true;
-            }
-            if redraw {
-                    draw_images(&mut di.content, &images, current);
-            }
-            plane.render()?;
+            },
+            Outcome::Shell(..) | Outcome::Quit => return Ok(outcome),
         }
     }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
 
-    if cmd.get_args().len() > 0 {
-        cmd.status();
+    // need a private channel to get at the file descriptor for poll()
+    let mut channel = DbusChannel::get_private(BusType::System)?;
+    channel.set_watch_enabled(true);
+    let conn = Connection::from(channel);
+
+    let backend = DbusBackend::new(&conn);
+    let (bus_tx, bus_rx) = mpsc::channel();
+    backend.subscribe(bus_tx)?;
+
+    let mut app = App::new();
+    // not worth failing for, it only holds conveniences
+    app.config = Config::load().unwrap_or_default();
+
+    let mut first = true;
+    while let Outcome::Shell(name, user) = ui(&mut app, &backend, &bus_rx, first)? {
+        first = false;
+        let session = backend.open_shell(&name, &user)
+            .and_then(|mut master| Ok(pty::relay(&mut master, &format!("Connected to {} as {}.", name, user))?));
+        if let Err(e) = session {
+            app.fail(format!("Shell in {} failed", name), e);
+        }
+        // the machine may have been shut down from within
+        app.refresh(&backend);
     }
 
     Ok(())
@@ -0,0 +1,294 @@
+// per image settings files of systemd-nspawn, see systemd.nspawn(5)
+use std::fs;
+use std::io;
+use std::path::{Path,PathBuf};
+
+// a .nspawn file kept line by line, so comments, unknown keys and their
+// order survive editing
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct Settings {
+    lines: Vec<String>,
+}
+
+fn section_name(line: &str) -> Option<&str> {
+    let line = line.trim();
+    if line.starts_with('[') && line.ends_with(']') {
+        Some(&line[1..line.len()-1])
+    } else {
+        None
+    }
+}
+
+fn assignment(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim();
+    if line.starts_with('#') || line.starts_with(';') {
+        return None;
+    }
+    let i = line.find('=')?;
+    Some((line[..i].trim(), line[i+1..].trim()))
+}
+
+impl Settings {
+
+    pub fn parse(text: &str) -> Settings {
+        Settings { lines: text.lines().map(|l| l.to_string()).collect() }
+    }
+
+    pub fn serialize(&self) -> String {
+        let mut text = self.lines.join("\n");
+        if !text.is_empty() {
+            text.push('\n');
+        }
+        text
+    }
+
+    pub fn load(path: &Path) -> io::Result<Settings> {
+        fs::read_to_string(path).map(|text| Settings::parse(&text))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.serialize())
+    }
+
+    // lines assigning key in section, a section may show up more than once
+    fn find(&self, section: &str, key: &str) -> Vec<usize> {
+        let mut current = None;
+        let mut found = Vec::new();
+        for (i, line) in self.lines.iter().enumerate() {
+            if let Some(name) = section_name(line) {
+                current = Some(name);
+            } else if current == Some(section) && assignment(line).map(|a| a.0) == Some(key) {
+                found.push(i);
+            }
+        }
+        found
+    }
+
+    // where new keys go: after the last assignment of the section, which
+    // gets appended if there is none
+    fn section_end(&mut self, section: &str) -> usize {
+        let mut current = None;
+        let mut end = None;
+        for (i, line) in self.lines.iter().enumerate() {
+            if let Some(name) = section_name(line) {
+                current = Some(name);
+                if name == section {
+                    end = Some(i + 1);
+                }
+            } else if current == Some(section) && assignment(line).is_some() {
+                end = Some(i + 1);
+            }
+        }
+        match end {
+            Some(end) => end,
+            None => {
+                if self.lines.last().is_some_and(|l| !l.trim().is_empty()) {
+                    self.lines.push(String::new());
+                }
+                self.lines.push(format!("[{}]", section));
+                self.lines.len()
+            },
+        }
+    }
+
+    // all values of a key that may be given more than once. An empty
+    // assignment resets the list, like systemd does it
+    pub fn get_all(&self, section: &str, key: &str) -> Vec<String> {
+        let mut values = Vec::new();
+        for i in self.find(section, key) {
+            match assignment(&self.lines[i]) {
+                Some((_, "")) => values.clear(),
+                Some((_, value)) => values.push(value.to_string()),
+                None => {},
+            }
+        }
+        values
+    }
+
+    // the last assignment wins
+    pub fn get(&self, section: &str, key: &str) -> Option<String> {
+        self.get_all(section, key).pop()
+    }
+
+    // replaces all assignments of key, the new ones go where the first of
+    // them was
+    pub fn set_all(&mut self, section: &str, key: &str, values: &[String]) {
+        let found = self.find(section, key);
+        let at = match found.first() {
+            Some(&i) => i,
+            None if values.is_empty() => return,
+            None => self.section_end(section),
+        };
+        for &i in found.iter().rev() {
+            self.lines.remove(i);
+        }
+        for (n, value) in values.iter().enumerate() {
+            self.lines.insert(at + n, format!("{}={}", key, value));
+        }
+    }
+}
+
+// the file systemd-nspawn uses for an image, the first found of the
+// directories and the one the image is in
+pub fn locate(dirs: &[PathBuf], image: Option<&Path>, name: &str) -> Option<PathBuf> {
+    let file = format!("{}.nspawn", name);
+    dirs.iter().map(|d| d.join(&file))
+        .chain(image.and_then(|p| p.parent()).map(|d| d.join(&file)))
+        .find(|p| p.is_file())
+}
+
+// yes/no for the values systemd takes as booleans, anything else as it is
+pub fn boolean(value: &str) -> &str {
+    match value {
+        "1" | "yes" | "y" | "true" | "t" | "on" => "yes",
+        "0" | "no" | "n" | "false" | "f" | "off" => "no",
+        _ => value,
+    }
+}
+
+// catches the typos systemd-nspawn would refuse to start with
+pub fn check(key: &str, value: &str) -> Result<(), String> {
+    match key {
+        // a - ignores a missing source, a + makes it relative to the image
+        "Bind" | "BindReadOnly" => {
+            let source = value.split(':').next().unwrap_or("");
+            let source = source.trim_start_matches('-').trim_start_matches('+');
+            if !source.starts_with('/') {
+                return Err(format!("'{}' does not start with an absolute path", value));
+            }
+        },
+        // [protocol:]host[:container]
+        "Port" => {
+            let mut parts: Vec<&str> = value.split(':').collect();
+            if parts.len() > 1 && (parts[0] == "tcp" || parts[0] == "udp") {
+                parts.remove(0);
+            }
+            if parts.len() > 2 || parts.iter().any(|p| !matches!(p.parse::<u16>(), Ok(n) if n > 0)) {
+                return Err(format!("'{}' is not a port like tcp:2222:22", value));
+            }
+        },
+        _ => {},
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testdir::TempDir;
+
+    // None removes the key, the default of systemd-nspawn applies then
+    fn set(settings: &mut Settings, section: &str, key: &str, value: Option<&str>) {
+        let values: Vec<String> = value.into_iter().map(|v| v.to_string()).collect();
+        settings.set_all(section, key, &values);
+    }
+
+    const SAMPLE: &str = "\
+# managed by hand
+[Exec]
+Boot=on
+# keep the host's users apart
+PrivateUsers=pick
+Environment=LANG=C.UTF-8
+
+[Files]
+Bind=/srv/src:/src
+Bind=
+Bind=/home/ludwig:/home/ludwig:norbind
+TemporaryFileSystem=/tmp
+
+; network
+[Network]
+VirtualEthernet=no
+";
+
+    #[test]
+    fn reads_values() {
+        let settings = Settings::parse(SAMPLE);
+        assert_eq!(settings.serialize(), SAMPLE);
+        assert_eq!(settings.get("Exec", "Boot").unwrap(), "on");
+        assert_eq!(boolean(&settings.get("Exec", "Boot").unwrap()), "yes");
+        assert_eq!(settings.get("Exec", "Environment").unwrap(), "LANG=C.UTF-8");
+        assert_eq!(settings.get_all("Files", "Bind"), vec!["/home/ludwig:/home/ludwig:norbind"]);
+        assert_eq!(settings.get("Network", "Zone"), None);
+        // keys only count in their own section
+        assert_eq!(settings.get("Network", "Boot"), None);
+        assert_eq!(Settings::parse("").serialize(), "");
+    }
+
+    #[test]
+    fn edits_keep_the_rest() {
+        let mut settings = Settings::parse(SAMPLE);
+        set(&mut settings, "Exec", "Boot", Some("no"));
+        set(&mut settings, "Exec", "PrivateUsers", None);
+        settings.set_all("Files", "Bind", &["/a:/a".to_string(), "/b:/b:ro".to_string()]);
+        set(&mut settings, "Network", "Zone", Some("dev"));
+        settings.set_all("Network", "Port", &["tcp:2222:22".to_string()]);
+        assert_eq!(settings.serialize(), "\
+# managed by hand
+[Exec]
+Boot=no
+# keep the host's users apart
+Environment=LANG=C.UTF-8
+
+[Files]
+Bind=/a:/a
+Bind=/b:/b:ro
+TemporaryFileSystem=/tmp
+
+; network
+[Network]
+VirtualEthernet=no
+Zone=dev
+Port=tcp:2222:22
+");
+    }
+
+    #[test]
+    fn adds_sections() {
+        let mut settings = Settings::parse("# nothing yet");
+        set(&mut settings, "Files", "Bind", None);
+        assert_eq!(settings.serialize(), "# nothing yet\n");
+        set(&mut settings, "Network", "Zone", Some("dev"));
+        set(&mut settings, "Exec", "Boot", Some("yes"));
+        assert_eq!(settings.serialize(), "# nothing yet\n\n[Network]\nZone=dev\n\n[Exec]\nBoot=yes\n");
+        let mut settings = Settings::default();
+        set(&mut settings, "Exec", "Boot", Some("yes"));
+        assert_eq!(settings.serialize(), "[Exec]\nBoot=yes\n");
+    }
+
+    #[test]
+    fn checks_values() {
+        assert!(check("Bind", "/srv:/srv:ro").is_ok());
+        assert!(check("Bind", "-/maybe").is_ok());
+        assert!(check("Bind", "srv:/srv").is_err());
+        assert!(check("Port", "tcp:2222:22").is_ok());
+        assert!(check("Port", "8080").is_ok());
+        assert!(check("Port", "sctp:1:2").is_err());
+        assert!(check("Port", "tcp:0").is_err());
+        assert!(check("Zone", "anything").is_ok());
+    }
+
+    #[test]
+    fn locates_file() {
+        let dir = TempDir::new("locate");
+        let (etc, run, pool) = (dir.join("etc"), dir.join("run"), dir.join("machines"));
+        for d in &[&etc, &run, &pool] {
+            fs::create_dir_all(d).unwrap();
+        }
+        let dirs = vec![etc.clone(), run.clone()];
+        let image = pool.join("leap");
+        assert_eq!(locate(&dirs, Some(&image), "leap"), None);
+        fs::write(pool.join("leap.nspawn"), SAMPLE).unwrap();
+        assert_eq!(locate(&dirs, Some(&image), "leap"), Some(pool.join("leap.nspawn")));
+        assert_eq!(locate(&dirs, None, "leap"), None);
+        fs::write(run.join("leap.nspawn"), SAMPLE).unwrap();
+        assert_eq!(locate(&dirs, Some(&image), "leap"), Some(run.join("leap.nspawn")));
+        Settings::parse(SAMPLE).save(&etc.join("leap.nspawn")).unwrap();
+        assert_eq!(locate(&dirs, Some(&image), "leap"), Some(etc.join("leap.nspawn")));
+        assert_eq!(Settings::load(&etc.join("leap.nspawn")).unwrap(), Settings::parse(SAMPLE));
+    }
+}
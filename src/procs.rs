@@ -0,0 +1,145 @@
+use std::time::Instant;
+
+use backend::Process;
+
+// one line of the process pane, cgroups have no pid
+#[derive(Clone,Debug,PartialEq)]
+pub struct Row {
+    pub depth: usize,
+    pub pid: Option<u32>,
+    pub text: String,
+}
+
+// the processes of a running machine, reloaded every now and then
+#[derive(Debug)]
+pub struct ProcessView {
+    pub name: String,
+    pub unit: String,
+    pub rows: Vec<Row>,
+    pub current: usize,
+    pub loaded: Instant,
+}
+
+impl ProcessView {
+
+    pub fn new(name: &str, unit: &str, processes: &[Process], now: Instant) -> ProcessView {
+        ProcessView { name: name.to_string(), unit: unit.to_string(), rows: tree(processes), current: 0, loaded: now }
+    }
+
+    // stays on the same process, or cgroup, if it's still there
+    pub fn reload(&mut self, processes: &[Process], now: Instant) {
+        let selected = self.selected().cloned();
+        self.rows = tree(processes);
+        self.loaded = now;
+        if let Some(sel) = selected {
+            let same = |r: &Row| if sel.pid.is_some() { r.pid == sel.pid } else { r.pid.is_none() && r.depth == sel.depth && r.text == sel.text };
+            if let Some(idx) = self.rows.iter().position(same) {
+                self.current = idx;
+            }
+        }
+        if self.current >= self.rows.len() {
+            self.current = self.rows.len().saturating_sub(1);
+        }
+    }
+
+    pub fn selected(&self) -> Option<&Row> {
+        self.rows.get(self.current)
+    }
+
+    pub fn up(&mut self) {
+        self.current = self.current.saturating_sub(1);
+    }
+
+    pub fn down(&mut self) {
+        if self.current + 1 < self.rows.len() {
+            self.current += 1;
+        }
+    }
+}
+
+// cgroups below the one of the unit, each followed by its processes.
+// The unit's own cgroup is the root, shown as /
+pub fn tree(processes: &[Process]) -> Vec<Row> {
+    let mut procs: Vec<(Vec<&str>, &Process)> = processes.iter()
+        .map(|p| (p.cgroup.split('/').filter(|c| !c.is_empty()).collect(), p)).collect();
+    let root = match procs.first() {
+        Some((first, _)) => procs.iter().fold(first.len(), |n, (c, _)| {
+            c.iter().zip(first.iter()).take(n).take_while(|&(a, b)| a == b).count()
+        }),
+        None => return Vec::new(),
+    };
+    procs.sort_by(|a, b| (&a.0[root..], a.1.pid).cmp(&(&b.0[root..], b.1.pid)));
+
+    let mut rows = vec![Row { depth: 0, pid: None, text: "/".to_string() }];
+    let mut prev: &[&str] = &[];
+    for &(ref cgroup, p) in &procs {
+        let path = &cgroup[root..];
+        let same = prev.iter().zip(path.iter()).take_while(|&(a, b)| a == b).count();
+        for (i, c) in path.iter().enumerate().skip(same) {
+            rows.push(Row { depth: i + 1, pid: None, text: c.to_string() });
+        }
+        prev = path;
+        let text = if p.command.is_empty() { p.pid.to_string() } else { format!("{} {}", p.pid, p.command) };
+        rows.push(Row { depth: path.len() + 1, pid: Some(p.pid), text });
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(cgroup: &str, pid: u32, command: &str) -> Process {
+        Process { cgroup: cgroup.to_string(), pid, command: command.to_string() }
+    }
+
+    fn lines(rows: &[Row]) -> Vec<String> {
+        rows.iter().map(|r| format!("{}{}", "  ".repeat(r.depth), r.text)).collect()
+    }
+
+    #[test]
+    fn builds_tree() {
+        let unit = "/machine.slice/systemd-nspawn@leap.service";
+        let procs = vec![
+            process(&format!("{}/payload/system.slice/sshd.service", unit), 400, "sshd"),
+            process(&format!("{}/payload/init.scope", unit), 101, "/usr/lib/systemd/systemd"),
+            process(&format!("{}/supervisor", unit), 100, "systemd-nspawn --boot"),
+            process(&format!("{}/payload/system.slice/cron.service", unit), 300, ""),
+            process(&format!("{}/payload/system.slice/sshd.service", unit), 399, "sshd -D"),
+        ];
+        assert_eq!(lines(&tree(&procs)), vec![
+            "/",
+            "  payload",
+            "    init.scope",
+            "      101 /usr/lib/systemd/systemd",
+            "    system.slice",
+            "      cron.service",
+            "        300",
+            "      sshd.service",
+            "        399 sshd -D",
+            "        400 sshd",
+            "  supervisor",
+            "    100 systemd-nspawn --boot",
+        ]);
+    }
+
+    #[test]
+    fn single_cgroup() {
+        let procs = vec![process("/machine.slice/machine-leap.scope", 7, "init")];
+        assert_eq!(lines(&tree(&procs)), vec!["/", "  7 init"]);
+        assert!(tree(&[]).is_empty());
+    }
+
+    #[test]
+    fn reload_keeps_selection() {
+        let now = Instant::now();
+        let mut view = ProcessView::new("leap", "leap.scope", &[process("/a", 1, "init"), process("/a", 5, "sh")], now);
+        view.down();
+        view.down();
+        assert_eq!(view.selected().unwrap().pid, Some(5));
+        view.reload(&[process("/a", 1, "init"), process("/a", 3, "ls"), process("/a", 5, "sh")], now);
+        assert_eq!(view.selected().unwrap().pid, Some(5));
+        view.reload(&[process("/a", 1, "init")], now);
+        assert_eq!(view.current, 1);
+    }
+}
@@ -0,0 +1,139 @@
+use libc;
+use std::fs::File;
+use std::io::{self,Read,Write};
+use std::mem;
+use std::os::unix::io::{AsRawFd,RawFd};
+use std::sync::atomic::{AtomicBool,Ordering};
+
+// Ctrl-] three times in a row ends the session, like in machinectl
+const ESCAPE: u8 = 0x1d;
+
+// puts the terminal into raw mode, the old settings come back on drop
+struct RawMode {
+    fd: RawFd,
+    saved: libc::termios,
+}
+
+impl RawMode {
+
+    fn enable(fd: RawFd) -> io::Result<RawMode> {
+        let mut saved: libc::termios = unsafe { mem::zeroed() };
+        if unsafe { libc::tcgetattr(fd, &mut saved) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut raw = saved;
+        unsafe { libc::cfmakeraw(&mut raw) };
+        if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(RawMode { fd, saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(self.fd, libc::TCSANOW, &self.saved) };
+    }
+}
+
+// set from the SIGWINCH handler, the relay loop passes the new size on
+static RESIZED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_winch(_: libc::c_int) {
+    RESIZED.store(true, Ordering::SeqCst);
+}
+
+// catches SIGWINCH while relaying, the old handler comes back on drop
+struct WinchHandler {
+    saved: libc::sigaction,
+}
+
+impl WinchHandler {
+
+    fn install() -> io::Result<WinchHandler> {
+        let mut action: libc::sigaction = unsafe { mem::zeroed() };
+        action.sa_sigaction = on_winch as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        unsafe { libc::sigemptyset(&mut action.sa_mask) };
+        let mut saved: libc::sigaction = unsafe { mem::zeroed() };
+        if unsafe { libc::sigaction(libc::SIGWINCH, &action, &mut saved) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(WinchHandler { saved })
+    }
+}
+
+impl Drop for WinchHandler {
+    fn drop(&mut self) {
+        unsafe { libc::sigaction(libc::SIGWINCH, &self.saved, ::std::ptr::null_mut()) };
+    }
+}
+
+fn copy_window_size(from: RawFd, to: RawFd) {
+    let mut ws: libc::winsize = unsafe { mem::zeroed() };
+    if unsafe { libc::ioctl(from, libc::TIOCGWINSZ, &mut ws) } == 0 {
+        unsafe { libc::ioctl(to, libc::TIOCSWINSZ, &ws) };
+    }
+}
+
+pub fn set_window_size(fd: RawFd, cols: usize, rows: usize) {
+    let ws = libc::winsize { ws_row: rows as libc::c_ushort, ws_col: cols as libc::c_ushort, ws_xpixel: 0, ws_ypixel: 0 };
+    unsafe { libc::ioctl(fd, libc::TIOCSWINSZ, &ws) };
+}
+
+// connect the terminal to the pty until the other side hangs up
+pub fn relay(pty: &mut File, banner: &str) -> io::Result<()> {
+    let stdin = io::stdin().as_raw_fd();
+    let mut stdout = io::stdout();
+    write!(stdout, "{}\r\nPress ^] three times to return to mat.\r\n", banner)?;
+    stdout.flush()?;
+
+    let _raw = RawMode::enable(stdin)?;
+    let _winch = WinchHandler::install()?;
+    RESIZED.store(true, Ordering::SeqCst);
+    let mut buf = [0u8; 4096];
+    let mut escapes = 0;
+    loop {
+        // the signal interrupts poll, so this runs right after a resize
+        if RESIZED.swap(false, Ordering::SeqCst) {
+            copy_window_size(stdin, pty.as_raw_fd());
+        }
+        let mut fds = [
+            libc::pollfd { fd: stdin, events: libc::POLLIN, revents: 0 },
+            libc::pollfd { fd: pty.as_raw_fd(), events: libc::POLLIN, revents: 0 },
+        ];
+        if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) } < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+        if fds[0].revents & libc::POLLIN != 0 {
+            // not through io::stdin(), its buffer would keep keys from the pty
+            let n = unsafe { libc::read(stdin, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+            if n <= 0 {
+                return Ok(());
+            }
+            let data = &buf[..n as usize];
+            for &b in data {
+                escapes = if b == ESCAPE { escapes + 1 } else { 0 };
+            }
+            if escapes >= 3 {
+                return Ok(());
+            }
+            pty.write_all(data)?;
+        }
+        if fds[1].revents & (libc::POLLIN | libc::POLLHUP | libc::POLLERR) != 0 {
+            match pty.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(n) => {
+                    stdout.write_all(&buf[..n])?;
+                    stdout.flush()?;
+                },
+                // the shell exited
+                Err(ref e) if e.raw_os_error() == Some(libc::EIO) => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
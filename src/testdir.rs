@@ -0,0 +1,34 @@
+// a directory for the files of a test, removed again when it's dropped
+use std::env;
+use std::fs;
+use std::ops::Deref;
+use std::path::PathBuf;
+use std::process;
+
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+
+    // named after the test, tests run in parallel
+    pub fn new(name: &str) -> TempDir {
+        let path = env::temp_dir().join(format!("mat-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+}
+
+impl Deref for TempDir {
+    type Target = PathBuf;
+    fn deref(&self) -> &PathBuf {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
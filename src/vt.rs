@@ -0,0 +1,510 @@
+use notcurses::{Input,Received,Key};
+
+// a small subset of what xterm understands, enough for getty, shells and less
+
+// 16 colors like the linux console, then the xterm 256 color cube and gray ramp
+fn palette(idx: u8) -> u32 {
+    const BASE: [u32; 16] = [
+        0x000000, 0xaa0000, 0x00aa00, 0xaa5500, 0x0000aa, 0xaa00aa, 0x00aaaa, 0xaaaaaa,
+        0x555555, 0xff5555, 0x55ff55, 0xffff55, 0x5555ff, 0xff55ff, 0x55ffff, 0xffffff,
+    ];
+    match idx {
+        0..=15 => BASE[idx as usize],
+        16..=231 => {
+            let i = idx as u32 - 16;
+            let level = |v: u32| if v == 0 { 0 } else { 55 + v * 40 };
+            (level(i / 36) << 16) | (level(i / 6 % 6) << 8) | level(i % 6)
+        },
+        _ => {
+            let v = 8 + (idx as u32 - 232) * 10;
+            (v << 16) | (v << 8) | v
+        },
+    }
+}
+
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub struct Attr {
+    // None is the default color of the pane
+    pub fg: Option<u32>,
+    pub bg: Option<u32>,
+    pub bold: bool,
+    pub reverse: bool,
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Cell {
+    pub ch: char,
+    pub attr: Attr,
+}
+
+const BLANK: Cell = Cell { ch: ' ', attr: Attr { fg: None, bg: None, bold: false, reverse: false } };
+
+#[derive(Debug,PartialEq)]
+enum State {
+    Ground,
+    Escape,
+    Csi(String),
+    // operating system command, e.g. the window title, ignored
+    Osc,
+    OscEscape,
+    // designate character set, the next byte is dropped
+    Charset,
+}
+
+pub struct Screen {
+    pub width: usize,
+    pub height: usize,
+    pub rows: Vec<Vec<Cell>>,
+    // column, row
+    pub cursor: (usize, usize),
+    pub cursor_visible: bool,
+    // answers to queries, to be written back to the pty
+    pub replies: Vec<u8>,
+    attr: Attr,
+    saved: (usize, usize),
+    // scroll region, inclusive
+    top: usize,
+    bottom: usize,
+    // the last column was written, the next character goes to a new line
+    wrap_pending: bool,
+    state: State,
+    utf8: Vec<u8>,
+}
+
+impl Screen {
+
+    pub fn new(width: usize, height: usize) -> Screen {
+        let (width, height) = (width.max(1), height.max(1));
+        Screen {
+            width, height,
+            rows: vec![vec![BLANK; width]; height],
+            cursor: (0, 0),
+            cursor_visible: true,
+            replies: Vec::new(),
+            attr: Attr::default(),
+            saved: (0, 0),
+            top: 0,
+            bottom: height - 1,
+            wrap_pending: false,
+            state: State::Ground,
+            utf8: Vec::new(),
+        }
+    }
+
+    // rows that no longer fit go off the top, so the cursor line stays
+    pub fn resize(&mut self, width: usize, height: usize) {
+        let (width, height) = (width.max(1), height.max(1));
+        let gone = (self.cursor.1 + 1).saturating_sub(height);
+        self.rows.drain(..gone);
+        self.rows.resize(height, vec![BLANK; width]);
+        for row in &mut self.rows {
+            row.resize(width, BLANK);
+        }
+        self.width = width;
+        self.height = height;
+        self.cursor = (self.cursor.0.min(width - 1), self.cursor.1 - gone);
+        self.saved = (self.saved.0.min(width - 1), self.saved.1.min(height - 1));
+        self.top = 0;
+        self.bottom = height - 1;
+        self.wrap_pending = false;
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            if b < 0x80 {
+                // a broken sequence
+                if !self.utf8.is_empty() {
+                    self.utf8.clear();
+                    self.input('\u{fffd}');
+                }
+                self.input(b as char);
+                continue;
+            }
+            self.utf8.push(b);
+            match ::std::str::from_utf8(&self.utf8) {
+                Ok(s) => {
+                    let c = s.chars().next().unwrap_or('\u{fffd}');
+                    self.utf8.clear();
+                    self.input(c);
+                },
+                Err(ref e) if e.error_len().is_some() => {
+                    self.utf8.clear();
+                    self.input('\u{fffd}');
+                },
+                // incomplete, wait for more
+                Err(_) => {},
+            }
+        }
+    }
+
+    fn input(&mut self, c: char) {
+        let state = ::std::mem::replace(&mut self.state, State::Ground);
+        match state {
+            State::Ground => self.ground(c),
+            State::Escape => self.escape(c),
+            State::Csi(mut params) => match c {
+                '\x20'..='\x3f' => {
+                    params.push(c);
+                    self.state = State::Csi(params);
+                },
+                '\x40'..='\x7e' => self.csi(&params, c),
+                // controls are executed in the middle of a sequence
+                '\x00'..='\x1f' => {
+                    self.ground(c);
+                    if self.state == State::Ground {
+                        self.state = State::Csi(params);
+                    }
+                },
+                _ => {},
+            },
+            State::Osc => match c {
+                '\x07' => {},
+                '\x1b' => self.state = State::OscEscape,
+                _ => self.state = State::Osc,
+            },
+            State::OscEscape | State::Charset => {},
+        }
+    }
+
+    fn ground(&mut self, c: char) {
+        match c {
+            '\x1b' => self.state = State::Escape,
+            '\r' => {
+                self.cursor.0 = 0;
+                self.wrap_pending = false;
+            },
+            '\n' | '\x0b' | '\x0c' => self.linefeed(),
+            '\x08' => {
+                self.cursor.0 = self.cursor.0.saturating_sub(1);
+                self.wrap_pending = false;
+            },
+            '\t' => self.cursor.0 = ((self.cursor.0 / 8 + 1) * 8).min(self.width - 1),
+            '\x00'..='\x1f' | '\x7f' => {},
+            c => self.put(c),
+        }
+    }
+
+    fn escape(&mut self, c: char) {
+        match c {
+            '[' => self.state = State::Csi(String::new()),
+            ']' => self.state = State::Osc,
+            '(' | ')' | '*' | '+' => self.state = State::Charset,
+            '7' => self.saved = self.cursor,
+            '8' => self.restore(),
+            'D' => self.linefeed(),
+            'E' => {
+                self.cursor.0 = 0;
+                self.linefeed();
+            },
+            'M' => {
+                if self.cursor.1 == self.top {
+                    self.scroll_down(1);
+                } else {
+                    self.cursor.1 = self.cursor.1.saturating_sub(1);
+                }
+            },
+            'c' => *self = Screen::new(self.width, self.height),
+            _ => {},
+        }
+    }
+
+    fn put(&mut self, c: char) {
+        if self.wrap_pending {
+            self.cursor.0 = 0;
+            self.linefeed();
+        }
+        self.rows[self.cursor.1][self.cursor.0] = Cell { ch: c, attr: self.attr };
+        if self.cursor.0 + 1 == self.width {
+            self.wrap_pending = true;
+        } else {
+            self.cursor.0 += 1;
+        }
+    }
+
+    fn linefeed(&mut self) {
+        self.wrap_pending = false;
+        if self.cursor.1 == self.bottom {
+            self.scroll_up(1);
+        } else if self.cursor.1 + 1 < self.height {
+            self.cursor.1 += 1;
+        }
+    }
+
+    fn blank(&self) -> Cell {
+        // erased cells keep the background color
+        Cell { ch: ' ', attr: Attr { bg: self.attr.bg, ..Attr::default() } }
+    }
+
+    fn scroll_up(&mut self, n: usize) {
+        for _ in 0..n.min(self.bottom - self.top + 1) {
+            self.rows.remove(self.top);
+            let row = vec![self.blank(); self.width];
+            self.rows.insert(self.bottom, row);
+        }
+    }
+
+    fn scroll_down(&mut self, n: usize) {
+        for _ in 0..n.min(self.bottom - self.top + 1) {
+            self.rows.remove(self.bottom);
+            let row = vec![self.blank(); self.width];
+            self.rows.insert(self.top, row);
+        }
+    }
+
+    fn restore(&mut self) {
+        self.cursor = (self.saved.0.min(self.width - 1), self.saved.1.min(self.height - 1));
+        self.wrap_pending = false;
+    }
+
+    fn erase(&mut self, row: usize, from: usize, to: usize) {
+        let blank = self.blank();
+        for cell in &mut self.rows[row][from..to.min(self.width)] {
+            *cell = blank;
+        }
+    }
+
+    fn csi(&mut self, params: &str, f: char) {
+        let private = params.starts_with('?');
+        let nums: Vec<usize> = params.trim_start_matches(['?', '>']).split(';').map(|p| p.parse().unwrap_or(0)).collect();
+        // missing and zero parameters mean the default
+        let n = |i: usize, default: usize| nums.get(i).cloned().filter(|&v| v > 0).unwrap_or(default);
+        let (col, row) = self.cursor;
+        self.wrap_pending = false;
+        match f {
+            'A' => self.cursor.1 = row.saturating_sub(n(0, 1)).max(if row >= self.top { self.top } else { 0 }),
+            'B' => self.cursor.1 = (row + n(0, 1)).min(if row <= self.bottom { self.bottom } else { self.height - 1 }),
+            'C' => self.cursor.0 = (col + n(0, 1)).min(self.width - 1),
+            'D' => self.cursor.0 = col.saturating_sub(n(0, 1)),
+            'E' => self.cursor = (0, (row + n(0, 1)).min(self.height - 1)),
+            'F' => self.cursor = (0, row.saturating_sub(n(0, 1))),
+            'G' | '`' => self.cursor.0 = (n(0, 1) - 1).min(self.width - 1),
+            'd' => self.cursor.1 = (n(0, 1) - 1).min(self.height - 1),
+            'H' | 'f' => self.cursor = ((n(1, 1) - 1).min(self.width - 1), (n(0, 1) - 1).min(self.height - 1)),
+            'J' => {
+                let (from, to) = match n(0, 0) {
+                    0 => {
+                        self.erase(row, col, self.width);
+                        (row + 1, self.height)
+                    },
+                    1 => {
+                        self.erase(row, 0, col + 1);
+                        (0, row)
+                    },
+                    _ => (0, self.height),
+                };
+                for r in from..to {
+                    self.erase(r, 0, self.width);
+                }
+            },
+            'K' => match n(0, 0) {
+                0 => self.erase(row, col, self.width),
+                1 => self.erase(row, 0, col + 1),
+                _ => self.erase(row, 0, self.width),
+            },
+            'L' | 'M' if row >= self.top && row <= self.bottom => {
+                let top = self.top;
+                self.top = row;
+                if f == 'L' { self.scroll_down(n(0, 1)) } else { self.scroll_up(n(0, 1)) }
+                self.top = top;
+                self.cursor.0 = 0;
+            },
+            '@' => {
+                let blank = self.blank();
+                for _ in 0..n(0, 1).min(self.width - col) {
+                    self.rows[row].pop();
+                    self.rows[row].insert(col, blank);
+                }
+            },
+            'P' => {
+                let blank = self.blank();
+                for _ in 0..n(0, 1).min(self.width - col) {
+                    self.rows[row].remove(col);
+                    self.rows[row].push(blank);
+                }
+            },
+            'X' => self.erase(row, col, col + n(0, 1)),
+            'S' => self.scroll_up(n(0, 1)),
+            'T' => self.scroll_down(n(0, 1)),
+            'm' => self.sgr(&nums),
+            'r' => {
+                let top = n(0, 1) - 1;
+                let bottom = n(1, self.height).min(self.height) - 1;
+                if top < bottom {
+                    self.top = top;
+                    self.bottom = bottom;
+                    self.cursor = (0, 0);
+                }
+            },
+            's' => self.saved = self.cursor,
+            'u' => self.restore(),
+            'n' if n(0, 0) == 6 => {
+                let reply = format!("\x1b[{};{}R", row + 1, col + 1);
+                self.replies.extend_from_slice(reply.as_bytes());
+            },
+            'h' | 'l' if private => {
+                let set = f == 'h';
+                for &mode in &nums {
+                    match mode {
+                        25 => self.cursor_visible = set,
+                        // alternate screen, there is no scrollback to protect so just clear
+                        47 | 1047 | 1049 => {
+                            if set {
+                                self.saved = self.cursor;
+                            }
+                            for r in 0..self.height {
+                                self.erase(r, 0, self.width);
+                            }
+                            if !set {
+                                self.restore();
+                            }
+                        },
+                        _ => {},
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn sgr(&mut self, nums: &[usize]) {
+        let mut i = 0;
+        while i < nums.len() {
+            match nums[i] {
+                0 => self.attr = Attr::default(),
+                1 => self.attr.bold = true,
+                22 => self.attr.bold = false,
+                7 => self.attr.reverse = true,
+                27 => self.attr.reverse = false,
+                c @ 30..=37 => self.attr.fg = Some(palette((c - 30) as u8)),
+                39 => self.attr.fg = None,
+                c @ 40..=47 => self.attr.bg = Some(palette((c - 40) as u8)),
+                49 => self.attr.bg = None,
+                c @ 90..=97 => self.attr.fg = Some(palette((c - 90 + 8) as u8)),
+                c @ 100..=107 => self.attr.bg = Some(palette((c - 100 + 8) as u8)),
+                c @ 38 | c @ 48 => {
+                    let color = match nums.get(i + 1) {
+                        Some(&5) => {
+                            i += 2;
+                            nums.get(i).map(|&v| palette(v as u8))
+                        },
+                        Some(&2) => {
+                            i += 4;
+                            match (nums.get(i - 2), nums.get(i - 1), nums.get(i)) {
+                                (Some(&r), Some(&g), Some(&b)) => Some(((r as u32 & 0xff) << 16) | ((g as u32 & 0xff) << 8) | (b as u32 & 0xff)),
+                                _ => None,
+                            }
+                        },
+                        _ => None,
+                    };
+                    if c == 38 { self.attr.fg = color } else { self.attr.bg = color }
+                },
+                _ => {},
+            }
+            i += 1;
+        }
+    }
+}
+
+// what a terminal would send for the key
+pub fn encode_key(input: &Input) -> Vec<u8> {
+    let seq: &[u8] = match input.received {
+        Received::Char(c) => {
+            if input.keymod.has_ctrl() && c.is_ascii_alphabetic() {
+                return vec![c.to_ascii_lowercase() as u8 & 0x1f];
+            }
+            let mut buf = [0u8; 4];
+            let mut bytes = c.encode_utf8(&mut buf).as_bytes().to_vec();
+            if input.keymod.has_alt() {
+                bytes.insert(0, 0x1b);
+            }
+            return bytes;
+        },
+        Received::Key(Key::Enter) => b"\r",
+        Received::Key(Key::Tab) => b"\t",
+        Received::Key(Key::Esc) => b"\x1b",
+        Received::Key(Key::Backspace) => b"\x7f",
+        Received::Key(Key::Up) => b"\x1b[A",
+        Received::Key(Key::Down) => b"\x1b[B",
+        Received::Key(Key::Right) => b"\x1b[C",
+        Received::Key(Key::Left) => b"\x1b[D",
+        Received::Key(Key::Home) => b"\x1b[H",
+        Received::Key(Key::End) => b"\x1b[F",
+        Received::Key(Key::Ins) => b"\x1b[2~",
+        Received::Key(Key::Del) => b"\x1b[3~",
+        Received::Key(Key::PgUp) => b"\x1b[5~",
+        Received::Key(Key::PgDown) => b"\x1b[6~",
+        Received::Key(Key::F01) => b"\x1bOP",
+        Received::Key(Key::F02) => b"\x1bOQ",
+        Received::Key(Key::F03) => b"\x1bOR",
+        Received::Key(Key::F04) => b"\x1bOS",
+        _ => b"",
+    };
+    seq.to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl Screen {
+        fn line(&self, row: usize) -> String {
+            self.rows[row].iter().map(|c| c.ch).collect::<String>().trim_end().to_string()
+        }
+    }
+
+    #[test]
+    fn text_wraps_and_scrolls() {
+        let mut s = Screen::new(4, 2);
+        s.feed(b"abcdef\r\nxy");
+        assert_eq!(s.line(0), "ef");
+        assert_eq!(s.line(1), "xy");
+        assert_eq!(s.cursor, (2, 1));
+    }
+
+    #[test]
+    fn cursor_and_erase() {
+        let mut s = Screen::new(10, 3);
+        s.feed(b"hello\x1b[1;3H\x1b[K\x1b[3;1Hend\x1b[2J");
+        assert_eq!(s.line(0), "");
+        assert_eq!(s.line(2), "");
+        s.feed(b"\x1b[2;2Hx\x1b[6n");
+        assert_eq!(s.line(1), " x");
+        assert_eq!(s.replies, b"\x1b[2;3R".to_vec());
+    }
+
+    #[test]
+    fn colors_and_utf8() {
+        let mut s = Screen::new(10, 1);
+        s.feed(b"\x1b[1;31m\xe2\x9c");
+        s.feed(b"\x93\x1b[0m.\x1b]0;title\x07!");
+        assert_eq!(s.line(0), "✓.!");
+        assert_eq!(s.rows[0][0].attr, Attr { fg: Some(0xaa0000), bold: true, ..Attr::default() });
+        assert_eq!(s.rows[0][1].attr, Attr::default());
+    }
+
+    #[test]
+    fn scroll_region() {
+        let mut s = Screen::new(3, 3);
+        s.feed(b"top\r\n\x1b[2;3r\x1b[2;1Ha\r\nb\r\nc");
+        assert_eq!(s.line(0), "top");
+        assert_eq!(s.line(1), "b");
+        assert_eq!(s.line(2), "c");
+    }
+
+    #[test]
+    fn resize_keeps_cursor_line() {
+        let mut s = Screen::new(4, 3);
+        s.feed(b"a\r\nb\r\ncdef");
+        s.resize(2, 2);
+        assert_eq!((s.width, s.height), (2, 2));
+        assert_eq!(s.line(0), "b");
+        assert_eq!(s.line(1), "cd");
+        assert_eq!(s.cursor, (1, 1));
+        s.resize(3, 4);
+        assert_eq!(s.line(1), "cd");
+        assert_eq!(s.line(3), "");
+        s.feed(b"\x1b[4;1Hz\r\n");
+        assert_eq!(s.line(3), "");
+        assert_eq!(s.line(2), "z");
+    }
+}